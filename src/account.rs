@@ -0,0 +1,154 @@
+use crate::elb::{self, ElbData};
+use crate::elbv2::{self, ElbV2Data};
+use crate::error::Error;
+use crate::models::{AppConfig, AwsAccount, LoadBalancerState};
+use crate::sts;
+use crate::utils;
+
+use aws_credential_types::provider::SharedCredentialsProvider;
+use std::fmt;
+use std::sync::Arc;
+
+/// Everything discovered in one account, with the credentials that were used
+/// so deletion runs as the same role. `errors` holds the regions and load
+/// balancers that could not be discovered.
+#[derive(Clone)]
+pub struct AccountData {
+    pub account_id: String,
+    pub credentials: SharedCredentialsProvider,
+    pub elbs: Vec<ElbData>,
+    pub elbv2s: Vec<ElbV2Data>,
+    pub errors: Vec<Error>,
+}
+
+impl fmt::Debug for AccountData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccountData")
+            .field("account_id", &self.account_id)
+            .field("elbs", &self.elbs)
+            .field("elbv2s", &self.elbv2s)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+/// Assumes the account's role and discovers its classic ELBs and ELBv2s.
+pub async fn discover_account(
+    conf: Arc<AppConfig>,
+    aws_account: AwsAccount,
+) -> Result<AccountData, Error> {
+    let account_id = utils::extract_account_id_from_role_arn(&aws_account.iam_role)
+        .ok_or_else(|| Error::Config(format!("invalid iam_role {}", aws_account.iam_role)))?;
+    let credentials = sts::assume_role(&aws_account.iam_role, conf.aws.session_duration_seconds)
+        .await
+        .map_err(|message| Error::Account {
+            account_id: account_id.clone(),
+            message,
+        })?;
+
+    let elb_task = tokio::spawn(elb::process_account(
+        account_id.clone(),
+        credentials.clone(),
+        Arc::clone(&conf),
+        aws_account.clone(),
+    ));
+    let elbv2_task = tokio::spawn(elbv2::process_account(
+        account_id.clone(),
+        credentials.clone(),
+        Arc::clone(&conf),
+        aws_account,
+    ));
+
+    let mut errors: Vec<Error> = vec![];
+    let elbs = match elb_task.await {
+        Ok((elbs, mut elb_errors)) => {
+            errors.append(&mut elb_errors);
+            elbs
+        }
+        Err(e) => {
+            errors.push(Error::Account {
+                account_id: account_id.clone(),
+                message: format!("error discovering classic ELBs: {}", e),
+            });
+            vec![]
+        }
+    };
+    let elbv2s = match elbv2_task.await {
+        Ok((elbv2s, mut elbv2_errors)) => {
+            errors.append(&mut elbv2_errors);
+            elbv2s
+        }
+        Err(e) => {
+            errors.push(Error::Account {
+                account_id: account_id.clone(),
+                message: format!("error discovering ELBv2s: {}", e),
+            });
+            vec![]
+        }
+    };
+
+    Ok(AccountData {
+        account_id,
+        credentials,
+        elbs,
+        elbv2s,
+        errors,
+    })
+}
+
+/// The inactive load balancers of an account, i.e. the ones a delete run
+/// would delete.
+pub fn inactive_load_balancers(account: &AccountData) -> AccountData {
+    AccountData {
+        account_id: account.account_id.clone(),
+        credentials: account.credentials.clone(),
+        elbs: account
+            .elbs
+            .iter()
+            .filter(|elb| elb.state == LoadBalancerState::Inactive)
+            .cloned()
+            .collect(),
+        elbv2s: account
+            .elbv2s
+            .iter()
+            .filter(|elbv2| elbv2.state == LoadBalancerState::Inactive)
+            .cloned()
+            .collect(),
+        errors: vec![],
+    }
+}
+
+/// Deletes the inactive load balancers of an account. Returns the name or
+/// ARN of every deleted load balancer, and an error for every one that was
+/// not deleted because something failed.
+pub async fn delete_load_balancers(
+    conf: Arc<AppConfig>,
+    account: AccountData,
+) -> Vec<Result<String, Error>> {
+    let elb_task = tokio::spawn(elb::process_elb(
+        account.elbs,
+        account.credentials.clone(),
+        account.account_id.clone(),
+        conf.archive_dir.clone(),
+    ));
+    let elbv2_task = tokio::spawn(elbv2::process_elbv2(
+        account.elbv2s,
+        account.credentials,
+        account.account_id.clone(),
+        conf.archive_dir.clone(),
+        conf.disable_deletion_protection,
+    ));
+
+    let (elb_result, elbv2_result) = tokio::join!(elb_task, elbv2_task);
+    let mut results: Vec<Result<String, Error>> = vec![];
+    for result in [elb_result, elbv2_result] {
+        match result {
+            Ok(mut deletions) => results.append(&mut deletions),
+            Err(e) => results.push(Err(Error::Account {
+                account_id: account.account_id.clone(),
+                message: format!("error deleting load balancers: {}", e),
+            })),
+        }
+    }
+    results
+}
@@ -1,52 +1,317 @@
+use crate::retry;
+
 use aws_sdk_cloudwatch::types::DateTime as CloudwatchDateTime;
 use aws_sdk_cloudwatch::{
-    model::{Metric, MetricDataQuery, MetricDataResult, MetricStat},
+    model::{Metric, MetricDataQuery, MetricDataResult, MetricStat, StatusCode},
     Client as CloudWatchClient,
 };
 use aws_smithy_types_convert::date_time::DateTimeExt;
 use chrono::{Duration, Utc};
+use std::collections::HashMap;
+
+/// CloudWatch keeps hourly datapoints for 455 days, anything older is gone.
+pub const MAX_LOOKBACK_DAYS: i64 = 455;
+
+/// Maximum number of queries (metrics and expressions) in a single
+/// GetMetricData request.
+pub const MAX_QUERIES_PER_REQUEST: usize = 500;
+
+/// Picks the finest period that CloudWatch still retains for the whole
+/// lookback window: 1-minute data is kept for 15 days, 5-minute data for
+/// 63 days and 1-hour data for 455 days.
+pub fn period_for_days(days: i64) -> Result<i32, String> {
+    if days < 1 {
+        return Err(format!("days must be at least 1, got {}", days));
+    }
 
+    let period = match days {
+        0..=15 => 60,
+        16..=63 => 300,
+        64..=MAX_LOOKBACK_DAYS => 3600,
+        _ => {
+            return Err(format!(
+                "days must be at most {} (CloudWatch retention), got {}",
+                MAX_LOOKBACK_DAYS, days
+            ))
+        }
+    };
+
+    Ok(period)
+}
+
+/// A set of metrics whose datapoints are summed into a single series, e.g. the
+/// HealthyHostCount of every target group behind one load balancer. Results
+/// are keyed by `key` so callers can map them back to their load balancer.
+#[derive(Clone)]
+pub struct MetricRequest {
+    pub key: String,
+    pub metrics: Vec<Metric>,
+    pub stat: String,
+}
+
+impl MetricRequest {
+    pub fn new(key: &str, metrics: Vec<Metric>, stat: &str) -> Self {
+        MetricRequest {
+            key: key.to_string(),
+            metrics,
+            stat: stat.to_string(),
+        }
+    }
+}
+
+/// Fetches every request using as few GetMetricData calls as possible.
+/// Requests with several metrics are aggregated server side with a metric math
+/// `SUM` expression. A request that does not fit in one call is split into
+/// several expressions whose results are concatenated.
 pub async fn get_metric_stats(
     cw_client: &CloudWatchClient,
-    metric: Metric,
+    requests: Vec<MetricRequest>,
     days: i64,
-) -> Option<MetricDataResult> {
-    let start_time = Utc::now() - Duration::days(days);
-    let end_time = Utc::now();
-    let start_time: CloudwatchDateTime = CloudwatchDateTime::from_chrono_utc(start_time);
-    let end_time: CloudwatchDateTime = CloudwatchDateTime::from_chrono_utc(end_time);
+) -> HashMap<String, Result<MetricDataResult, String>> {
+    let mut results: HashMap<String, Result<MetricDataResult, String>> = HashMap::new();
+
+    let period = match period_for_days(days) {
+        Ok(period) => period,
+        Err(e) => {
+            for request in requests {
+                results.insert(request.key, Err(e.clone()));
+            }
+            return results;
+        }
+    };
+
+    // Every (key, metrics) group becomes one returned series. A group of more
+    // than one metric also needs a SUM expression, so it can hold at most
+    // MAX_QUERIES_PER_REQUEST - 1 metrics.
+    let mut groups: Vec<(String, &str, &[Metric])> = vec![];
+    for request in requests.iter() {
+        if request.metrics.is_empty() {
+            continue;
+        }
+        for metrics in request.metrics.chunks(MAX_QUERIES_PER_REQUEST - 1) {
+            groups.push((request.key.clone(), request.stat.as_str(), metrics));
+        }
+    }
+
+    let mut batches: Vec<(Vec<MetricDataQuery>, HashMap<String, String>)> = vec![];
+    let mut queries: Vec<MetricDataQuery> = vec![];
+    let mut ids: HashMap<String, String> = HashMap::new();
+
+    for (index, (key, stat, metrics)) in groups.into_iter().enumerate() {
+        let group_size = if metrics.len() > 1 {
+            metrics.len() + 1
+        } else {
+            1
+        };
+        if queries.len() + group_size > MAX_QUERIES_PER_REQUEST {
+            batches.push((queries, ids));
+            queries = vec![];
+            ids = HashMap::new();
+        }
+
+        let id = format!("q{}", index);
+        if metrics.len() == 1 {
+            queries.push(metric_stat_query(&id, &metrics[0], stat, period, true));
+        } else {
+            let mut component_ids = vec![];
+            for (metric_index, metric) in metrics.iter().enumerate() {
+                let component_id = format!("{}_{}", id, metric_index);
+                queries.push(metric_stat_query(
+                    &component_id,
+                    metric,
+                    stat,
+                    period,
+                    false,
+                ));
+                component_ids.push(component_id);
+            }
+            queries.push(
+                MetricDataQuery::builder()
+                    .id(id.clone())
+                    .expression(format!("SUM([{}])", component_ids.join(",")))
+                    .return_data(true)
+                    .build(),
+            );
+        }
+        ids.insert(id, key);
+    }
+    if !queries.is_empty() {
+        batches.push((queries, ids));
+    }
+
+    for (queries, ids) in batches {
+        match get_metric_data(cw_client, queries, days).await {
+            Ok(mut batch_results) => {
+                for (id, key) in ids {
+                    let result = match batch_results.remove(&id) {
+                        Some(result) => result,
+                        None => MetricDataResult::builder().id(id).build(),
+                    };
+                    merge_metric_result(&mut results, key, Ok(result));
+                }
+            }
+            Err(e) => {
+                for (_, key) in ids {
+                    merge_metric_result(&mut results, key, Err(e.clone()));
+                }
+            }
+        }
+    }
 
-    let metric_data_query = MetricDataQuery::builder()
-        .id("m1")
+    results
+}
+
+fn metric_stat_query(
+    id: &str,
+    metric: &Metric,
+    stat: &str,
+    period: i32,
+    return_data: bool,
+) -> MetricDataQuery {
+    MetricDataQuery::builder()
+        .id(id)
         .metric_stat(
             MetricStat::builder()
                 .metric(metric.clone())
-                .period(60)
-                .stat("Minimum")
+                .period(period)
+                .stat(stat)
                 .build(),
         )
-        .build();
-
-    let response = cw_client
-        .get_metric_data()
-        .metric_data_queries(metric_data_query)
-        .start_time(start_time)
-        .end_time(end_time)
-        .send()
+        .return_data(return_data)
+        .build()
+}
+
+/// Merges the result of one group into the result for its key. A failure of
+/// any group fails the whole key.
+fn merge_metric_result(
+    results: &mut HashMap<String, Result<MetricDataResult, String>>,
+    key: String,
+    result: Result<MetricDataResult, String>,
+) {
+    let merged = match (results.remove(&key), result) {
+        (None, result) => result,
+        (Some(Err(e)), _) | (Some(Ok(_)), Err(e)) => Err(e),
+        (Some(Ok(existing)), Ok(result)) => {
+            let mut timestamps = existing.timestamps().unwrap_or_default().to_vec();
+            timestamps.extend_from_slice(result.timestamps().unwrap_or_default());
+            let mut values = existing.values().unwrap_or_default().to_vec();
+            values.extend_from_slice(result.values().unwrap_or_default());
+            Ok(MetricDataResult::builder()
+                .set_id(existing.id().map(|id| id.to_string()))
+                .set_timestamps(Some(timestamps))
+                .set_values(Some(values))
+                .status_code(StatusCode::Complete)
+                .build())
+        }
+    };
+    results.insert(key, merged);
+}
+
+/// Runs GetMetricData for the given queries, following `next_token` until
+/// every page has been read, and merges the pages of each query by id.
+pub async fn get_metric_data(
+    cw_client: &CloudWatchClient,
+    queries: Vec<MetricDataQuery>,
+    days: i64,
+) -> Result<HashMap<String, MetricDataResult>, String> {
+    let start_time = Utc::now() - Duration::days(days);
+    let end_time = Utc::now();
+    let start_time: CloudwatchDateTime = CloudwatchDateTime::from_chrono_utc(start_time);
+    let end_time: CloudwatchDateTime = CloudwatchDateTime::from_chrono_utc(end_time);
+
+    let mut timestamps: HashMap<String, Vec<CloudwatchDateTime>> = HashMap::new();
+    let mut values: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut labels: HashMap<String, String> = HashMap::new();
+    let mut next_token = None;
+
+    loop {
+        let response = retry::send("CloudWatch GetMetricData", || {
+            cw_client
+                .get_metric_data()
+                .set_metric_data_queries(Some(queries.clone()))
+                .start_time(start_time)
+                .end_time(end_time)
+                .set_next_token(next_token.clone())
+                .send()
+        })
         .await;
 
-    match response {
-        Ok(output) => {
-            if let Some(metric_data_results) = output.metric_data_results() {
-                if let Some(metric_data_result) = metric_data_results.first() {
-                    return Some(metric_data_result.clone());
-                }
+        let output = match response {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("Error getting metric stats: {}", e);
+                return Err(e.to_string());
+            }
+        };
+
+        for result in output.metric_data_results().unwrap_or_default() {
+            let id = result.id().unwrap_or_default().to_string();
+
+            if let Some(StatusCode::InternalError) = result.status_code() {
+                return Err(format!("CloudWatch returned an internal error for {}", id));
+            }
+
+            timestamps
+                .entry(id.clone())
+                .or_default()
+                .extend_from_slice(result.timestamps().unwrap_or_default());
+            values
+                .entry(id.clone())
+                .or_default()
+                .extend_from_slice(result.values().unwrap_or_default());
+            if let Some(label) = result.label() {
+                labels.insert(id, label.to_string());
             }
-            None
         }
-        Err(e) => {
-            eprintln!("Error getting metric stats: {}", e);
-            None
+
+        next_token = output.next_token().map(|token| token.to_string());
+        if next_token.is_none() {
+            break;
         }
     }
+
+    let mut results = HashMap::new();
+    for (id, values) in values {
+        let result = MetricDataResult::builder()
+            .id(id.clone())
+            .set_label(labels.remove(&id))
+            .set_timestamps(timestamps.remove(&id))
+            .set_values(Some(values))
+            .status_code(StatusCode::Complete)
+            .build();
+        results.insert(id, result);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_finest_retained_period() {
+        assert_eq!(period_for_days(1), Ok(60));
+        assert_eq!(period_for_days(15), Ok(60));
+        assert_eq!(period_for_days(16), Ok(300));
+        assert_eq!(period_for_days(63), Ok(300));
+        assert_eq!(period_for_days(64), Ok(3600));
+        assert_eq!(period_for_days(MAX_LOOKBACK_DAYS), Ok(3600));
+    }
+
+    #[test]
+    fn rejects_lookbacks_outside_retention() {
+        assert_eq!(
+            period_for_days(0),
+            Err("days must be at least 1, got 0".to_string())
+        );
+        assert_eq!(
+            period_for_days(-3),
+            Err("days must be at least 1, got -3".to_string())
+        );
+        assert_eq!(
+            period_for_days(456),
+            Err("days must be at most 455 (CloudWatch retention), got 456".to_string())
+        );
+    }
 }
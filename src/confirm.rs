@@ -0,0 +1,143 @@
+use crate::account::AccountData;
+
+use std::io::{self, Write};
+
+/// One row of the confirmation table.
+struct Candidate {
+    lb_type: String,
+    id: String,
+    region: String,
+    vpc_id: String,
+    idle_days: String,
+}
+
+fn candidates(account: &AccountData) -> Vec<Candidate> {
+    let elbs = account.elbs.iter().map(|elb| Candidate {
+        lb_type: "classic".to_string(),
+        id: elb.name.clone(),
+        region: elb.region.to_string(),
+        vpc_id: elb.vpc_id.clone(),
+        idle_days: elb.idle_days.clone(),
+    });
+    let elbv2s = account.elbv2s.iter().map(|elbv2| Candidate {
+        lb_type: elbv2.lb_type.clone(),
+        id: elbv2.arn.clone(),
+        region: elbv2.region.to_string(),
+        vpc_id: elbv2.vpc_id.clone(),
+        idle_days: elbv2.idle_days.clone(),
+    });
+    elbs.chain(elbv2s).collect()
+}
+
+fn print_table(account_id: &str, candidates: &[Candidate]) {
+    let headers = [
+        "#",
+        "type",
+        "region",
+        "vpc_id",
+        "idle_days",
+        "load balancer",
+    ];
+    let rows = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            [
+                (i + 1).to_string(),
+                candidate.lb_type.clone(),
+                candidate.region.clone(),
+                candidate.vpc_id.clone(),
+                candidate.idle_days.clone(),
+                candidate.id.clone(),
+            ]
+        })
+        .collect::<Vec<[String; 6]>>();
+
+    let mut widths = headers.map(|header| header.len());
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    println!(
+        "\nAccount {}: {} load balancers to delete",
+        account_id,
+        candidates.len()
+    );
+    let header_row = headers.map(|header| header.to_string());
+    for row in std::iter::once(&header_row).chain(rows.iter()) {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Prints `question` and reads one answer from stdin, lowercased. `None` when
+/// stdin is closed.
+fn ask(question: &str) -> Option<String> {
+    print!("{} ", question);
+    io::stdout().flush().ok()?;
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(answer.trim().to_lowercase()),
+    }
+}
+
+/// Shows the load balancers about to be deleted in an account and asks the
+/// operator to confirm all of them, confirm each one, or abort. Returns the
+/// confirmed load balancers, or `None` when the run is aborted.
+pub fn confirm_account(account: &AccountData) -> Option<AccountData> {
+    let candidates = candidates(account);
+    if candidates.is_empty() {
+        return Some(account.clone());
+    }
+    print_table(&account.account_id, &candidates);
+
+    loop {
+        let answer = ask("Delete [a]ll, confirm [e]ach, or [q]uit without deleting anything?")?;
+        match answer.as_str() {
+            "a" | "all" => return Some(account.clone()),
+            "e" | "each" => break,
+            "q" | "quit" | "abort" => return None,
+            _ => println!("Please answer a, e or q"),
+        }
+    }
+
+    let mut confirmed = AccountData {
+        account_id: account.account_id.clone(),
+        credentials: account.credentials.clone(),
+        elbs: vec![],
+        elbv2s: vec![],
+        errors: vec![],
+    };
+    for elb in account.elbs.iter() {
+        if confirm_one("classic ELB", &elb.name)? {
+            confirmed.elbs.push(elb.clone());
+        }
+    }
+    for elbv2 in account.elbv2s.iter() {
+        if confirm_one("ELBv2", &elbv2.arn)? {
+            confirmed.elbv2s.push(elbv2.clone());
+        }
+    }
+    Some(confirmed)
+}
+
+/// `None` when the operator aborts.
+fn confirm_one(family: &str, id: &str) -> Option<bool> {
+    loop {
+        let answer = ask(&format!("Delete {} {}? [y]es, [n]o, [q]uit", family, id))?;
+        match answer.as_str() {
+            "y" | "yes" => return Some(true),
+            "n" | "no" => return Some(false),
+            "q" | "quit" | "abort" => return None,
+            _ => println!("Please answer y, n or q"),
+        }
+    }
+}
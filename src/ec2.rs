@@ -0,0 +1,125 @@
+use crate::retry;
+
+use aws_sdk_ec2::{
+    model::{Filter, State},
+    Client as Ec2Client,
+};
+use std::collections::HashMap;
+
+/// Returns the number of Gateway Load Balancer endpoints that are connected
+/// (or waiting to be accepted) to the endpoint services of this account, keyed
+/// by Gateway Load Balancer ARN.
+pub async fn get_gwlb_endpoint_counts(
+    ec2_client: &Ec2Client,
+) -> Result<HashMap<String, usize>, String> {
+    let mut service_gwlbs: HashMap<String, Vec<String>> = HashMap::new();
+    let mut next_token = None;
+
+    loop {
+        let resp = retry::send("EC2 DescribeVpcEndpointServiceConfigurations", || {
+            ec2_client
+                .describe_vpc_endpoint_service_configurations()
+                .set_next_token(next_token.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error describing endpoint services: {}", e))?;
+
+        for service in resp.service_configurations().unwrap_or_default() {
+            let gwlb_arns = service.gateway_load_balancer_arns().unwrap_or_default();
+            if let (Some(service_id), false) = (service.service_id(), gwlb_arns.is_empty()) {
+                service_gwlbs.insert(service_id.to_string(), gwlb_arns.to_vec());
+            }
+        }
+        next_token = resp.next_token().map(|token| token.to_string());
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    if service_gwlbs.is_empty() {
+        return Ok(counts);
+    }
+
+    let mut filter = Filter::builder().name("service-id");
+    for service_id in service_gwlbs.keys() {
+        filter = filter.values(service_id);
+    }
+    let filter = filter.build();
+    let mut next_token = None;
+
+    loop {
+        let resp = retry::send("EC2 DescribeVpcEndpointConnections", || {
+            ec2_client
+                .describe_vpc_endpoint_connections()
+                .filters(filter.clone())
+                .set_next_token(next_token.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error describing endpoint connections: {}", e))?;
+
+        for connection in resp.vpc_endpoint_connections().unwrap_or_default() {
+            let connected = matches!(
+                connection.vpc_endpoint_state(),
+                Some(State::Available) | Some(State::Pending) | Some(State::PendingAcceptance)
+            );
+            if !connected {
+                continue;
+            }
+            let gwlb_arns = connection
+                .service_id()
+                .and_then(|service_id| service_gwlbs.get(service_id));
+            for gwlb_arn in gwlb_arns.into_iter().flatten() {
+                *counts.entry(gwlb_arn.to_string()).or_default() += 1;
+            }
+        }
+        next_token = resp.next_token().map(|token| token.to_string());
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Returns the tags of every VPC in the region keyed by VPC id.
+pub async fn get_vpc_tags(
+    ec2_client: &Ec2Client,
+) -> Result<HashMap<String, HashMap<String, String>>, String> {
+    let mut vpc_tags: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut next_token = None;
+
+    loop {
+        let resp = retry::send("EC2 DescribeVpcs", || {
+            ec2_client
+                .describe_vpcs()
+                .set_next_token(next_token.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error describing VPCs: {}", e))?;
+
+        for vpc in resp.vpcs().unwrap_or_default() {
+            let tags = vpc
+                .tags()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|tag| {
+                    tag.key()
+                        .map(|key| (key.to_string(), tag.value().unwrap_or_default().to_string()))
+                })
+                .collect();
+            if let Some(vpc_id) = vpc.vpc_id() {
+                vpc_tags.insert(vpc_id.to_string(), tags);
+            }
+        }
+        next_token = resp.next_token().map(|token| token.to_string());
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(vpc_tags)
+}
@@ -1,24 +1,35 @@
-use crate::cloudwatch::get_metric_stats;
-use crate::models::{LoadBalancerState, RunOption};
+use crate::cloudwatch::{get_metric_stats, MetricRequest};
+use crate::ec2::get_vpc_tags;
+use crate::error::Error;
+use crate::filters;
+use crate::models::{AppConfig, AwsAccount, LoadBalancerState, MetricRule, ScoringConfig};
+use crate::retry;
+use crate::scoring;
+use crate::snapshot::{take_elb_snapshot, write_snapshot};
+use crate::sts;
 use crate::utils;
 
-use aws_config::meta::region::RegionProviderChain;
+use aws_credential_types::provider::SharedCredentialsProvider;
 use aws_sdk_cloudwatch::{
-    model::{Dimension, Metric},
+    model::{Dimension, Metric, MetricDataResult},
     Client as CloudWatchClient,
 };
-use aws_sdk_elasticloadbalancing::model::LoadBalancerDescription as LoadBalancer;
+use aws_sdk_ec2::Client as Ec2Client;
+use aws_sdk_elasticloadbalancing::model::{
+    LoadBalancerDescription as LoadBalancer, Tag, TagKeyOnly,
+};
 use aws_sdk_elasticloadbalancing::output::DeleteLoadBalancerOutput as DeleteOutput;
 use aws_sdk_elasticloadbalancing::Client as ELBClient;
-use aws_sdk_iam::Credentials;
-use aws_sdk_sts::types::DateTime as StsDateTime;
-use aws_sdk_sts::Client as StsClient;
 use aws_types::region::Region;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
-use tokio::sync::Semaphore;
+
+/// Stands in for the VPC id of classic ELBs outside any VPC, i.e. left over
+/// from EC2-Classic. It can be listed in `include_vpc_ids`/`exclude_vpc_ids`.
+pub const NO_VPC: &str = "no-vpc";
 
 #[derive(Clone)]
 pub struct ElbData {
@@ -26,6 +37,17 @@ pub struct ElbData {
     pub state: LoadBalancerState,
     pub region: Region,
     pub vpc_id: String,
+    pub created_time: Option<DateTime<Utc>>,
+    pub last_active_at: Option<DateTime<Utc>>,
+    pub idle_days: String,
+    pub tags: HashMap<String, String>,
+    /// Whether `tags` could be described. When they could not, `tags` is
+    /// empty but the load balancer may still carry tags, marks included.
+    pub tags_known: bool,
+    /// What each metric rule saw, for judged load balancers.
+    pub evidence: Vec<String>,
+    /// Hash of the load balancer's configuration and tags.
+    pub config_hash: String,
 }
 
 impl fmt::Debug for ElbData {
@@ -35,196 +57,433 @@ impl fmt::Debug for ElbData {
             .field("state", &self.state)
             .field("region", &self.region)
             .field("vpc_id", &self.vpc_id)
+            .field("created_time", &self.created_time)
+            .field("last_active_at", &self.last_active_at)
+            .field("idle_days", &self.idle_days)
+            .field("tags", &self.tags)
+            .field("tags_known", &self.tags_known)
+            .field("evidence", &self.evidence)
+            .field("config_hash", &self.config_hash)
             .finish()
     }
 }
 
 impl ElbData {
-    pub fn new(name: &str, state: LoadBalancerState, region: Region, vpc_id: String) -> Self {
+    pub fn new(
+        name: &str,
+        state: LoadBalancerState,
+        region: Region,
+        vpc_id: String,
+        created_time: Option<DateTime<Utc>>,
+    ) -> Self {
         ElbData {
             name: name.to_string(),
             state,
             region,
             vpc_id,
+            created_time,
+            last_active_at: None,
+            idle_days: String::new(),
+            tags: HashMap::new(),
+            tags_known: false,
+            evidence: vec![],
+            config_hash: String::new(),
         }
     }
 
-    pub fn to_string(&mut self) -> String {
+    pub fn to_csv(&self) -> String {
         format!(
-            "{},{},{},{}",
-            self.name, self.state, self.region, self.vpc_id
+            "{},{},{},{},{},{},{},{}",
+            self.name,
+            utils::csv_field(&self.state.to_string()),
+            self.region,
+            self.vpc_id,
+            utils::format_time(&self.created_time),
+            utils::format_time(&self.last_active_at),
+            self.idle_days,
+            utils::csv_field(&utils::format_tags(&self.tags))
         )
     }
 }
 
-pub async fn process_account(
-    account_id: &str,
-    run_option: RunOption,
-    days: i64,
-    iam_role: &str,
-    vpc_ids: Vec<String>,
-    regions: Vec<String>,
-) -> () {
-    let regions = utils::parse_regions_arg(&regions);
-    let vpc_ids = utils::parse_vpc_ids_arg(&vpc_ids);
-
-    let region_provider = RegionProviderChain::default_provider().or_else("ap-southeast-1");
-
-    let config = aws_config::from_env().region(region_provider).load().await;
-    let sts_client: StsClient = StsClient::new(&config);
-
-    let assumed_role = sts_client
-        .assume_role()
-        .role_arn(iam_role)
-        .role_session_name(&format!("lb_janitor_assumerole_session"))
-        .send()
-        .await;
+/// Discovers and judges the classic ELBs of an account in every configured
+/// region and writes the reports.
+/// The part of a classic ELB's configuration that `apply` compares with the
+/// plan, serialized to JSON so the hash doesn't depend on how the SDK prints
+/// its types.
+#[derive(Serialize)]
+struct ElbDescription {
+    scheme: Option<String>,
+    subnets: Vec<String>,
+    security_groups: Vec<String>,
+    listeners: Vec<ElbListenerDescription>,
+    health_check: Option<String>,
+    instances: Vec<String>,
+}
 
-    let assumed_role = assumed_role.unwrap();
-    let credentials = assumed_role.credentials().unwrap();
-    let access_key_id = credentials.access_key_id().unwrap();
-    let secret_access_key = credentials.secret_access_key().unwrap();
-    let session_token = credentials.session_token().unwrap();
-    let expiry: StsDateTime = *credentials.expiration().unwrap();
-    let expiry: SystemTime = SystemTime::try_from(expiry).unwrap();
-
-    let credentials = Credentials::new(
-        access_key_id,
-        secret_access_key,
-        Some(session_token.to_string()),
-        Some(expiry),
-        "AWS",
-    );
+#[derive(Serialize)]
+struct ElbListenerDescription {
+    protocol: Option<String>,
+    port: i32,
+    instance_protocol: Option<String>,
+    instance_port: i32,
+    certificate: Option<String>,
+    policies: Vec<String>,
+}
+
+fn sorted(values: Option<&[String]>) -> Vec<String> {
+    let mut values = values.unwrap_or_default().to_vec();
+    values.sort();
+    values
+}
+
+fn elb_description(lb: &LoadBalancer) -> String {
+    let listeners = lb
+        .listener_descriptions()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|description| {
+            let listener = description.listener()?;
+            Some(ElbListenerDescription {
+                protocol: listener.protocol().map(|p| p.to_string()),
+                port: listener.load_balancer_port(),
+                instance_protocol: listener.instance_protocol().map(|p| p.to_string()),
+                instance_port: listener.instance_port(),
+                certificate: listener.ssl_certificate_id().map(|id| id.to_string()),
+                policies: sorted(description.policy_names()),
+            })
+        })
+        .collect();
+    let mut instances: Vec<String> = lb
+        .instances()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|instance| instance.instance_id().map(|id| id.to_string()))
+        .collect();
+    instances.sort();
+
+    let description = ElbDescription {
+        scheme: lb.scheme().map(|scheme| scheme.to_string()),
+        subnets: sorted(lb.subnets()),
+        security_groups: sorted(lb.security_groups()),
+        listeners,
+        health_check: lb.health_check().map(|check| {
+            format!(
+                "{} every {}s, timeout {}s, healthy {}, unhealthy {}",
+                check.target().unwrap_or_default(),
+                check.interval(),
+                check.timeout(),
+                check.healthy_threshold(),
+                check.unhealthy_threshold()
+            )
+        }),
+        instances,
+    };
+    serde_json::to_string(&description).unwrap_or_default()
+}
+
+pub async fn process_account(
+    account_id: String,
+    credentials: SharedCredentialsProvider,
+    conf: Arc<AppConfig>,
+    aws_account: AwsAccount,
+) -> (Vec<ElbData>, Vec<Error>) {
+    let regions = utils::parse_regions_arg(&aws_account.regions);
 
     let mut tasks = Vec::new();
-    let mut inactive_elb_data: Vec<ElbData> = vec![];
+    let mut elb_data: Vec<ElbData> = vec![];
+    let mut errors: Vec<Error> = vec![];
 
     for region in regions {
         let elb_task = tokio::spawn(process_region(
-            region,
+            region.clone(),
             credentials.clone(),
-            days,
-            vpc_ids.clone(),
+            Arc::clone(&conf),
+            aws_account.clone(),
         ));
-        tasks.push(elb_task);
+        tasks.push((region, elb_task));
     }
 
-    for task in tasks {
-        let elb = task.await.unwrap();
-
-        let mut elb = elb
-            .into_iter()
-            .filter(|elb| elb.state == LoadBalancerState::Inactive)
-            .collect::<Vec<ElbData>>();
+    for (region, task) in tasks {
+        match task.await {
+            Ok((mut elb, mut region_errors)) => {
+                elb_data.append(&mut elb);
+                errors.append(&mut region_errors);
+            }
+            Err(e) => errors.push(Error::Region {
+                region: region.to_string(),
+                message: format!("error discovering classic ELBs: {}", e),
+            }),
+        }
+    }
 
-        inactive_elb_data.append(&mut elb);
+    let inactive_elb_data = elb_data
+        .iter()
+        .filter(|elb| elb.state == LoadBalancerState::Inactive)
+        .cloned()
+        .collect::<Vec<ElbData>>();
+    let undetermined_elb_data = elb_data
+        .iter()
+        .filter(|elb| elb.state.is_undetermined())
+        .cloned()
+        .collect::<Vec<ElbData>>();
+    let skipped_elb_data = elb_data
+        .iter()
+        .filter(|elb| elb.state.is_skipped())
+        .cloned()
+        .collect::<Vec<ElbData>>();
+    let filtered_elb_data = elb_data
+        .iter()
+        .filter(|elb| elb.state.is_filtered())
+        .cloned()
+        .collect::<Vec<ElbData>>();
+
+    println!(
+        "Account {}: {} classic ELBs, {} inactive, {} undetermined, {} skipped, {} filtered",
+        account_id,
+        elb_data.len(),
+        inactive_elb_data.len(),
+        undetermined_elb_data.len(),
+        skipped_elb_data.len(),
+        filtered_elb_data.len()
+    );
+    for elb in undetermined_elb_data.iter() {
+        println!("Undetermined classic ELB: {} {}", elb.name, elb.state);
     }
 
-    match run_option {
-        RunOption::List => {
-            let mut to_write: Vec<String> = vec![];
-            to_write.push("name,state,region,vpc_id".to_string());
-            for elb_data in inactive_elb_data.iter_mut() {
-                let line = elb_data.to_string();
-                to_write.push(line);
-            }
+    let file_name = format!("outputs/{}_inactive_elbs.csv", &account_id);
+    write_report(file_name.as_str(), &inactive_elb_data);
 
-            let file_name = format!("outputs/{}_inactive_elbs.csv", &account_id);
-            if let Err(e) = utils::write_csv(file_name.as_str(), to_write) {
-                println!("Error writing to csv file! {}", e);
-            }
-        }
-        RunOption::Delete => {
-            let mut tasks = Vec::new();
+    let file_name = format!("outputs/{}_undetermined_elbs.csv", &account_id);
+    write_report(file_name.as_str(), &undetermined_elb_data);
 
-            let elb_task = tokio::spawn(process_elb(inactive_elb_data));
+    let file_name = format!("outputs/{}_skipped_elbs.csv", &account_id);
+    write_report(file_name.as_str(), &skipped_elb_data);
 
-            tasks.push(elb_task);
+    let file_name = format!("outputs/{}_filtered_elbs.csv", &account_id);
+    write_report(file_name.as_str(), &filtered_elb_data);
 
-            futures::future::join_all(tasks).await;
-        }
-        RunOption::Unknown => {
-            panic!("Run option invalid!");
-        }
+    (elb_data, errors)
+}
+
+fn write_report(file_name: &str, elb_data: &[ElbData]) {
+    let mut to_write: Vec<String> = vec![];
+    to_write
+        .push("name,state,region,vpc_id,created_time,last_active_at,idle_days,tags".to_string());
+    for elb in elb_data.iter() {
+        to_write.push(elb.to_csv());
+    }
+
+    if let Err(e) = utils::write_csv(file_name, to_write) {
+        println!("Error writing to csv file! {}", e);
     }
 }
 
 pub async fn process_region(
     region: Region,
-    credentials: Credentials,
-    days: i64,
-    vpc_ids: HashMap<String, bool>,
-) -> Vec<ElbData> {
+    credentials: SharedCredentialsProvider,
+    conf: Arc<AppConfig>,
+    aws_account: AwsAccount,
+) -> (Vec<ElbData>, Vec<Error>) {
     let config = aws_config::from_env()
+        .retry_config(retry::sdk_retry_config())
         .credentials_provider(credentials)
-        .region(region)
+        .region(region.clone())
         .load()
         .await;
 
     let elb_client = ELBClient::new(&config);
+    let ec2_client = Ec2Client::new(&config);
     let cw_client = CloudWatchClient::new(&config);
 
-    let elb_lbs = get_elb_load_balancers(&elb_client).await;
-    let elb_data: Arc<Mutex<Vec<ElbData>>> = Arc::new(Mutex::new(vec![]));
-    let sem = Arc::new(Semaphore::new(10));
+    let elb_lbs = match get_elb_load_balancers(&elb_client).await {
+        Ok(elb_lbs) => elb_lbs,
+        Err(e) => {
+            let error = Error::Region {
+                region: region.to_string(),
+                message: format!("error listing classic ELBs: {}", e),
+            };
+            return (vec![], vec![error]);
+        }
+    };
+    let lb_names = elb_lbs
+        .iter()
+        .filter_map(|lb| lb.load_balancer_name())
+        .map(|lb_name| lb_name.to_string())
+        .collect::<Vec<String>>();
+    let tags = get_elb_tags(&elb_client, &lb_names).await;
+
+    let vpc_tags =
+        if aws_account.include_vpc_tags.is_empty() && aws_account.exclude_vpc_tags.is_empty() {
+            Ok(HashMap::new())
+        } else {
+            get_vpc_tags(&ec2_client).await
+        };
 
-    let mut tasks = Vec::new();
+    // Load balancers that are outside the selected VPCs, excluded by name,
+    // ARN or tags, or are too new are decided without reading their metrics.
+    let mut prejudged: HashMap<String, LoadBalancerState> = HashMap::new();
+    let mut requests: Vec<MetricRequest> = vec![];
+    let no_tags = HashMap::new();
+    for lb in elb_lbs.iter() {
+        let lb_name = match lb.load_balancer_name() {
+            Some(lb_name) => lb_name,
+            None => continue,
+        };
+        let created_time = utils::to_chrono_time(lb.created_time());
+        let lb_tags = tags
+            .as_ref()
+            .map(|tags| tags.get(lb_name).unwrap_or(&no_tags));
+        let vpc_id = lb.vpc_id().unwrap_or(NO_VPC);
+        let lb_vpc_tags = vpc_tags
+            .as_ref()
+            .map(|tags| tags.get(vpc_id).unwrap_or(&no_tags));
+        let state = filters::vpc_filter_state(vpc_id, lb_vpc_tags, &aws_account).or_else(|| {
+            filters::state_before_metrics(
+                lb_name,
+                None,
+                &created_time,
+                lb_tags,
+                &conf,
+                &aws_account,
+            )
+        });
+        if let Some(state) = state {
+            prejudged.insert(lb_name.to_string(), state);
+            continue;
+        }
+        requests.append(&mut elb_metric_requests(lb_name, &conf.scoring));
+    }
 
-    for lb in elb_lbs {
-        let cw_client = cw_client.clone();
-        let lb_name = lb.load_balancer_name().unwrap().to_string();
-        let vpc_ids = vpc_ids.clone();
-        let vpc_id = lb.vpc_id().unwrap().to_string();
-        let dns_name = lb.dns_name().unwrap().to_string();
+    println!(
+        "Fetching {} metrics for {} ELBs in {}",
+        requests.len(),
+        elb_lbs.len(),
+        region
+    );
+    let stats = get_metric_stats(&cw_client, requests, conf.days).await;
 
-        let region_string = utils::extract_region_from_elb_dns(&dns_name).unwrap();
-        let region = Region::new(region_string);
-        let elb_data = Arc::clone(&elb_data);
-        let sem = Arc::clone(&sem);
+    let mut elb_data: Vec<ElbData> = vec![];
+    let mut errors: Vec<Error> = vec![];
 
-        let task = async move {
-            let _perm = sem.acquire_owned().await;
-            println!("Processing ELB: {}", lb_name);
-            let state = get_elb_lb_state(lb_name.to_string(), &cw_client, days).await;
-            if let Some(state) = state {
-                let mut elb_data = elb_data.lock().unwrap();
-                if vpc_ids.len() > 0 && vpc_ids.contains_key(vpc_id.as_str()) {
-                    elb_data.push(ElbData::new(lb_name.as_str(), state, region, vpc_id));
-                } else {
-                    elb_data.push(ElbData::new(lb_name.as_str(), state, region, vpc_id));
-                }
+    for lb in elb_lbs {
+        let lb_name = match lb.load_balancer_name() {
+            Some(lb_name) => lb_name.to_string(),
+            None => {
+                errors.push(Error::Region {
+                    region: region.to_string(),
+                    message: "classic ELB without a name".to_string(),
+                });
+                continue;
             }
         };
-        tasks.push(task);
-    }
+        let vpc_id = lb.vpc_id().unwrap_or(NO_VPC).to_string();
 
-    let mut futures = Vec::new();
-    for task in tasks {
-        futures.push(task);
-    }
-    futures::future::join_all(futures).await;
+        let created_time = utils::to_chrono_time(lb.created_time());
 
-    let elb_data = elb_data.lock().unwrap();
+        println!("Processing ELB: {}", lb_name);
+        let judged = !prejudged.contains_key(&lb_name);
+        let state = match prejudged.remove(&lb_name) {
+            Some(state) => state,
+            None => get_elb_lb_state(&lb_name, &stats, &conf.scoring),
+        };
+        let mut elb = ElbData::new(
+            lb_name.as_str(),
+            state,
+            region.clone(),
+            vpc_id,
+            created_time,
+        );
+        if let Ok(tags) = &tags {
+            elb.tags = tags.get(&lb_name).cloned().unwrap_or_default();
+            elb.tags_known = true;
+        }
+        elb.config_hash =
+            utils::config_hash(&[elb_description(&lb), utils::format_tags(&elb.tags)]);
+        if judged {
+            elb.last_active_at = get_elb_last_active_at(&lb_name, &stats, &conf.scoring);
+            elb.evidence = get_elb_evidence(&lb_name, &stats, &conf.scoring);
+            elb.idle_days = utils::idle_days(&elb.last_active_at, &elb.created_time, conf.days);
+        }
+
+        if elb.state.is_undetermined() {
+            errors.push(Error::LoadBalancer {
+                id: elb.name.clone(),
+                message: elb.state.to_string(),
+            });
+        }
+        elb_data.push(elb);
+    }
 
-    elb_data.to_vec()
+    (elb_data, errors)
 }
 
-pub async fn process_elb(elbs: Vec<ElbData>) -> Vec<DeleteOutput> {
-    let deletion_results: Arc<Mutex<Vec<DeleteOutput>>> = Arc::new(Mutex::new(vec![]));
+pub async fn process_elb(
+    elbs: Vec<ElbData>,
+    credentials: SharedCredentialsProvider,
+    account_id: String,
+    archive_dir: String,
+) -> Vec<Result<String, Error>> {
+    if let Err(e) = sts::verify_account(&credentials, &account_id).await {
+        println!("Skipping ELB deletion in account {}: {}", account_id, e);
+        return vec![Err(Error::Account {
+            account_id,
+            message: format!("classic ELBs not deleted: {}", e),
+        })];
+    }
+
+    let deletion_results: Arc<Mutex<Vec<Result<String, Error>>>> = Arc::new(Mutex::new(vec![]));
     let mut tasks = Vec::new();
 
     for elb in elbs {
+        if elb.state != LoadBalancerState::Inactive {
+            println!("Skipping ELB deletion: {} is {}", elb.name, elb.state);
+            continue;
+        }
+
         let region = elb.region;
         let name = elb.name;
 
-        let config = aws_config::from_env().region(region).load().await;
+        let config = aws_config::from_env()
+            .retry_config(retry::sdk_retry_config())
+            .credentials_provider(credentials.clone())
+            .region(region.clone())
+            .load()
+            .await;
         let client = ELBClient::new(&config);
 
         let deletion_results = Arc::clone(&deletion_results);
+        let account_id = account_id.clone();
+        let archive_dir = archive_dir.clone();
 
         let task = async move {
             println!("Processing ELB deletion: {}", name);
-            let res = delete_elb(&name, &client).await;
+            let snapshot = take_elb_snapshot(&client, &account_id, region.as_ref(), &name)
+                .await
+                .and_then(|snapshot| write_snapshot(&archive_dir, &snapshot));
+            match snapshot {
+                Ok(file_name) => println!("Backed up ELB {} to {}", name, file_name),
+                Err(e) => {
+                    println!("Skipping ELB deletion: could not back up {}: {}", name, e);
+                    let mut deletion_results = deletion_results.lock().unwrap();
+                    deletion_results.push(Err(Error::LoadBalancer {
+                        id: name,
+                        message: format!("not deleted, could not back up: {}", e),
+                    }));
+                    return;
+                }
+            }
+
+            let res = match delete_elb(&name, &client).await {
+                Ok(_) => Ok(name),
+                Err(e) => {
+                    println!("Error deleting ELB {}: {}", name, e);
+                    Err(Error::LoadBalancer {
+                        id: name,
+                        message: format!("error deleting: {}", e),
+                    })
+                }
+            };
             let mut deletion_results = deletion_results.lock().unwrap();
             deletion_results.push(res);
         };
@@ -242,17 +501,19 @@ pub async fn process_elb(elbs: Vec<ElbData>) -> Vec<DeleteOutput> {
     deletion_results.to_vec()
 }
 
-async fn get_elb_load_balancers(client: &ELBClient) -> Vec<LoadBalancer> {
+async fn get_elb_load_balancers(client: &ELBClient) -> Result<Vec<LoadBalancer>, String> {
     let mut lbs = Vec::new();
     let mut next_marker = None;
 
     loop {
-        let resp = client
-            .describe_load_balancers()
-            .set_marker(next_marker)
-            .send()
-            .await
-            .unwrap();
+        let resp = retry::send("ELB DescribeLoadBalancers", || {
+            client
+                .describe_load_balancers()
+                .set_marker(next_marker.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| e.to_string())?;
 
         lbs.extend(resp.load_balancer_descriptions.unwrap_or_default());
         next_marker = resp.next_marker;
@@ -260,50 +521,220 @@ async fn get_elb_load_balancers(client: &ELBClient) -> Vec<LoadBalancer> {
             break;
         }
     }
-    lbs
+    Ok(lbs)
 }
 
-async fn get_elb_lb_state(
-    arn: String,
-    cw_client: &CloudWatchClient,
-    days: i64,
-) -> Option<LoadBalancerState> {
-    let lb_value = arn.split(':').last().unwrap();
-
-    let dimensions = Dimension::builder()
-        .name("LoadBalancerName")
-        .value(lb_value.to_string())
-        .build();
-
-    let metric = Metric::builder()
-        .namespace("AWS/ELB")
-        .metric_name("HealthyHostCount")
-        .set_dimensions(Some(vec![dimensions]))
-        .build();
-
-    let stats = get_metric_stats(&cw_client, metric, days).await;
-
-    match stats {
-        Some(stats) => {
-            let values = stats.values().unwrap_or(&[]);
-            let sum: f64 = values.iter().sum();
-            if sum > 0.0 {
-                Some(LoadBalancerState::Active)
-            } else {
-                Some(LoadBalancerState::Inactive)
+/// Returns the tags of every load balancer keyed by name. DescribeTags takes
+/// at most 20 load balancers per call.
+pub async fn get_elb_tags(
+    client: &ELBClient,
+    lb_names: &[String],
+) -> Result<HashMap<String, HashMap<String, String>>, String> {
+    let mut tags: HashMap<String, HashMap<String, String>> = HashMap::new();
+
+    for lb_names in lb_names.chunks(20) {
+        let resp = retry::send("ELB DescribeTags", || {
+            client
+                .describe_tags()
+                .set_load_balancer_names(Some(lb_names.to_vec()))
+                .send()
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for description in resp.tag_descriptions().unwrap_or_default() {
+            let lb_tags = description
+                .tags()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|tag| {
+                    tag.key()
+                        .map(|key| (key.to_string(), tag.value().unwrap_or_default().to_string()))
+                })
+                .collect();
+            if let Some(lb_name) = description.load_balancer_name() {
+                tags.insert(lb_name.to_string(), lb_tags);
             }
         }
-        None => Some(LoadBalancerState::Inactive),
     }
+
+    Ok(tags)
 }
 
-async fn delete_elb(name: &str, client: &ELBClient) -> DeleteOutput {
-    let out = client
-        .delete_load_balancer()
-        .load_balancer_name(name)
-        .send()
-        .await
-        .unwrap();
+fn elb_metric_key(lb_name: &str, rule: &MetricRule) -> String {
+    format!("{}|{}|{}", lb_name, rule.metric_name, rule.stat)
+}
+
+fn elb_metric_requests(lb_name: &str, scoring: &ScoringConfig) -> Vec<MetricRequest> {
+    let mut requests = vec![];
+
+    for rule in &scoring.classic {
+        let dimensions = Dimension::builder()
+            .name("LoadBalancerName")
+            .value(lb_name.to_string())
+            .build();
+
+        let metric = Metric::builder()
+            .namespace("AWS/ELB")
+            .metric_name(&rule.metric_name)
+            .set_dimensions(Some(vec![dimensions]))
+            .build();
+
+        requests.push(MetricRequest::new(
+            &elb_metric_key(lb_name, rule),
+            vec![metric],
+            &rule.stat,
+        ));
+    }
+
+    requests
+}
+
+fn get_elb_lb_state(
+    lb_name: &str,
+    stats: &HashMap<String, Result<MetricDataResult, String>>,
+    scoring: &ScoringConfig,
+) -> LoadBalancerState {
+    let mut score = 0.0;
+    let mut failures: Vec<String> = vec![];
+
+    for rule in &scoring.classic {
+        match stats.get(&elb_metric_key(lb_name, rule)) {
+            Some(Ok(stats)) => {
+                let values = stats.values().unwrap_or(&[]);
+                score += scoring::score_metric(rule, values);
+            }
+            Some(Err(e)) => failures.push(format!("{}: {}", rule.metric_name, e)),
+            None => (),
+        }
+    }
+
+    scoring::state_from_score(score, &failures, scoring)
+}
+
+fn get_elb_last_active_at(
+    lb_name: &str,
+    stats: &HashMap<String, Result<MetricDataResult, String>>,
+    scoring: &ScoringConfig,
+) -> Option<DateTime<Utc>> {
+    scoring
+        .classic
+        .iter()
+        .filter_map(|rule| match stats.get(&elb_metric_key(lb_name, rule)) {
+            Some(Ok(stats)) => scoring::last_active_at(rule, stats, scoring),
+            _ => None,
+        })
+        .max()
+}
+
+fn get_elb_evidence(
+    lb_name: &str,
+    stats: &HashMap<String, Result<MetricDataResult, String>>,
+    scoring: &ScoringConfig,
+) -> Vec<String> {
+    scoring
+        .classic
+        .iter()
+        .filter_map(|rule| match stats.get(&elb_metric_key(lb_name, rule)) {
+            Some(Ok(stats)) => Some(scoring::evidence(rule, stats.values().unwrap_or(&[]))),
+            _ => None,
+        })
+        .collect()
+}
+
+pub async fn add_elb_tags(
+    client: &ELBClient,
+    name: &str,
+    tags: &HashMap<String, String>,
+) -> Result<(), String> {
+    let tags = tags
+        .iter()
+        .map(|(key, value)| Tag::builder().key(key).value(value).build())
+        .collect::<Vec<Tag>>();
+    retry::send("ELB AddTags", || {
+        client
+            .add_tags()
+            .load_balancer_names(name)
+            .set_tags(Some(tags.clone()))
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error tagging {}: {}", name, e))?;
+    Ok(())
+}
+
+pub async fn remove_elb_tags(client: &ELBClient, name: &str, keys: &[&str]) -> Result<(), String> {
+    let keys = keys
+        .iter()
+        .map(|key| TagKeyOnly::builder().key(*key).build())
+        .collect::<Vec<TagKeyOnly>>();
+    retry::send("ELB RemoveTags", || {
+        client
+            .remove_tags()
+            .load_balancer_names(name)
+            .set_tags(Some(keys.clone()))
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error untagging {}: {}", name, e))?;
+    Ok(())
+}
+
+async fn delete_elb(name: &str, client: &ELBClient) -> Result<DeleteOutput, String> {
+    let out = retry::send("ELB DeleteLoadBalancer", || {
+        client
+            .delete_load_balancer()
+            .load_balancer_name(name)
+            .send()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
     println!("Deleted Classic Load Balancer: {:?}", name);
-    out
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use aws_sdk_elasticloadbalancing::model::{
+        HealthCheck, Instance, Listener, ListenerDescription,
+    };
+
+    #[test]
+    fn describes_the_configuration_as_json() {
+        let lb = LoadBalancer::builder()
+            .scheme("internet-facing")
+            .subnets("subnet-b")
+            .subnets("subnet-a")
+            .security_groups("sg-1")
+            .listener_descriptions(
+                ListenerDescription::builder()
+                    .listener(
+                        Listener::builder()
+                            .protocol("HTTP")
+                            .load_balancer_port(80)
+                            .instance_protocol("HTTP")
+                            .instance_port(8080)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .health_check(
+                HealthCheck::builder()
+                    .target("HTTP:8080/health")
+                    .interval(30)
+                    .timeout(5)
+                    .healthy_threshold(3)
+                    .unhealthy_threshold(2)
+                    .build(),
+            )
+            .instances(Instance::builder().instance_id("i-2").build())
+            .instances(Instance::builder().instance_id("i-1").build())
+            .build();
+        assert_eq!(
+            elb_description(&lb),
+            r#"{"scheme":"internet-facing","subnets":["subnet-a","subnet-b"],"security_groups":["sg-1"],"listeners":[{"protocol":"HTTP","port":80,"instance_protocol":"HTTP","instance_port":8080,"certificate":null,"policies":[]}],"health_check":"HTTP:8080/health every 30s, timeout 5s, healthy 3, unhealthy 2","instances":["i-1","i-2"]}"#
+        );
+    }
 }
@@ -1,25 +1,38 @@
-use crate::cloudwatch::get_metric_stats;
-use crate::models::{LoadBalancerState, RunOption};
+use crate::cloudwatch::{get_metric_stats, MetricRequest};
+use crate::ec2::{get_gwlb_endpoint_counts, get_vpc_tags};
+use crate::error::Error;
+use crate::filters;
+use crate::models::{
+    AppConfig, AwsAccount, LoadBalancerState, MetricDimension, MetricRule, ScoringConfig,
+};
+use crate::retry;
+use crate::scoring;
+use crate::snapshot::{take_elbv2_snapshot, write_snapshot};
+use crate::sts;
 use crate::utils;
 
-use aws_config::meta::region::RegionProviderChain;
+use aws_credential_types::provider::SharedCredentialsProvider;
 use aws_sdk_cloudwatch::{
-    model::{Dimension, Metric},
+    model::{Dimension, Metric, MetricDataResult},
     Client as CloudWatchClient,
 };
-use aws_sdk_elasticloadbalancingv2::model::LoadBalancer as LoadBalancerV2;
+use aws_sdk_ec2::Client as Ec2Client;
+use aws_sdk_elasticloadbalancingv2::model::{
+    LoadBalancer as LoadBalancerV2, LoadBalancerAttribute, LoadBalancerTypeEnum, Tag, TargetGroup,
+};
 use aws_sdk_elasticloadbalancingv2::output::DeleteLoadBalancerOutput as DeleteOutput;
 use aws_sdk_elasticloadbalancingv2::Client as ELBv2Client;
-use aws_sdk_iam::Credentials;
-use aws_sdk_sts::types::DateTime as StsDateTime;
-use aws_sdk_sts::Client as StsClient;
 use aws_types::region::Region;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
 use std::collections::HashMap;
-use std::convert::TryFrom;
 use std::fmt;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
-use tokio::sync::Semaphore;
+
+/// Load balancers whose listeners, attributes and targets are described at
+/// the same time during discovery.
+const MAX_CONCURRENT_DESCRIBES: usize = 8;
 
 #[derive(Clone)]
 pub struct ElbV2Data {
@@ -27,6 +40,21 @@ pub struct ElbV2Data {
     pub state: LoadBalancerState,
     pub region: Region,
     pub vpc_id: String,
+    pub created_time: Option<DateTime<Utc>>,
+    pub last_active_at: Option<DateTime<Utc>>,
+    pub idle_days: String,
+    pub tags: HashMap<String, String>,
+    /// Whether `tags` could be described. When they could not, `tags` is
+    /// empty but the load balancer may still carry tags, marks included.
+    pub tags_known: bool,
+    /// What each metric rule saw, for judged load balancers.
+    pub evidence: Vec<String>,
+    /// Hash of the load balancer's configuration and tags.
+    pub config_hash: String,
+    pub lb_type: String,
+    pub listener_actions: String,
+    pub target_types: String,
+    pub deletion_protection: Option<bool>,
 }
 
 impl fmt::Debug for ElbV2Data {
@@ -36,198 +64,626 @@ impl fmt::Debug for ElbV2Data {
             .field("state", &self.state)
             .field("region", &self.region)
             .field("vpc_id", &self.vpc_id)
+            .field("created_time", &self.created_time)
+            .field("last_active_at", &self.last_active_at)
+            .field("idle_days", &self.idle_days)
+            .field("tags", &self.tags)
+            .field("tags_known", &self.tags_known)
+            .field("evidence", &self.evidence)
+            .field("config_hash", &self.config_hash)
+            .field("lb_type", &self.lb_type)
+            .field("listener_actions", &self.listener_actions)
+            .field("target_types", &self.target_types)
+            .field("deletion_protection", &self.deletion_protection)
             .finish()
     }
 }
 
 impl ElbV2Data {
-    pub fn new(arn: &str, state: LoadBalancerState, region: Region, vpc_id: String) -> Self {
+    pub fn new(
+        arn: &str,
+        state: LoadBalancerState,
+        region: Region,
+        vpc_id: String,
+        created_time: Option<DateTime<Utc>>,
+        lb_type: &str,
+    ) -> Self {
         ElbV2Data {
             arn: arn.to_string(),
             state,
             region,
             vpc_id,
+            created_time,
+            last_active_at: None,
+            idle_days: String::new(),
+            tags: HashMap::new(),
+            tags_known: false,
+            evidence: vec![],
+            config_hash: String::new(),
+            lb_type: lb_type.to_string(),
+            listener_actions: String::new(),
+            target_types: String::new(),
+            deletion_protection: None,
         }
     }
 
-    pub fn to_string(&mut self) -> String {
+    pub fn to_csv(&self) -> String {
         format!(
-            "{},{},{},{}",
-            self.arn, self.state, self.region, self.vpc_id
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.arn,
+            utils::csv_field(&self.state.to_string()),
+            self.region,
+            self.vpc_id,
+            utils::format_time(&self.created_time),
+            utils::format_time(&self.last_active_at),
+            self.idle_days,
+            utils::csv_field(&utils::format_tags(&self.tags)),
+            self.lb_type,
+            utils::csv_field(&self.listener_actions),
+            utils::csv_field(&self.target_types),
+            self.deletion_protection
+                .map(|enabled| enabled.to_string())
+                .unwrap_or_default()
         )
     }
 }
 
-pub async fn process_account(
-    account_id: &str,
-    run_option: RunOption,
-    days: i64,
-    iam_role: &str,
-    vpc_ids: Vec<String>,
-    regions: Vec<String>,
-) -> () {
-    let regions = utils::parse_regions_arg(&regions);
-    let vpc_ids = utils::parse_vpc_ids_arg(&vpc_ids);
-
-    let region_provider = RegionProviderChain::default_provider().or_else("ap-southeast-1");
-
-    let config = aws_config::from_env().region(region_provider).load().await;
-    let sts_client: StsClient = StsClient::new(&config);
-
-    let assumed_role = sts_client
-        .assume_role()
-        .role_arn(iam_role)
-        .role_session_name(&format!("lb_janitor_assumerole_session"))
-        .send()
-        .await;
+/// Discovers and judges the ELBv2s of an account in every configured region
+/// and writes the reports.
+/// The part of an ELBv2's configuration that `apply` compares with the plan,
+/// with its target groups, serialized to JSON so the hash doesn't depend on
+/// how the SDK prints its types.
+#[derive(Serialize)]
+struct ElbV2Description {
+    scheme: Option<String>,
+    ip_address_type: Option<String>,
+    subnets: Vec<String>,
+    security_groups: Vec<String>,
+    target_groups: Vec<TargetGroupDescription>,
+}
 
-    let assumed_role = assumed_role.unwrap();
-    let credentials = assumed_role.credentials().unwrap();
-    let access_key_id = credentials.access_key_id().unwrap();
-    let secret_access_key = credentials.secret_access_key().unwrap();
-    let session_token = credentials.session_token().unwrap();
-    let expiry: StsDateTime = *credentials.expiration().unwrap();
-    let expiry: SystemTime = SystemTime::try_from(expiry).unwrap();
-
-    let credentials = Credentials::new(
-        access_key_id,
-        secret_access_key,
-        Some(session_token.to_string()),
-        Some(expiry),
-        "AWS",
-    );
+#[derive(Serialize)]
+struct TargetGroupDescription {
+    arn: Option<String>,
+    protocol: Option<String>,
+    port: Option<i32>,
+    target_type: Option<String>,
+    health_check: String,
+}
+
+fn elbv2_description(lb: &LoadBalancerV2, target_groups: Option<&[TargetGroup]>) -> String {
+    let mut subnets: Vec<String> = lb
+        .availability_zones()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|zone| zone.subnet_id().map(|id| id.to_string()))
+        .collect();
+    subnets.sort();
+    let mut security_groups = lb.security_groups().unwrap_or_default().to_vec();
+    security_groups.sort();
+    let mut target_groups: Vec<TargetGroupDescription> = target_groups
+        .unwrap_or_default()
+        .iter()
+        .map(|tg| TargetGroupDescription {
+            arn: tg.target_group_arn().map(|arn| arn.to_string()),
+            protocol: tg.protocol().map(|protocol| protocol.as_str().to_string()),
+            port: tg.port(),
+            target_type: tg.target_type().map(|t| t.as_str().to_string()),
+            health_check: format!(
+                "{} {} {}",
+                tg.health_check_protocol()
+                    .map(|protocol| protocol.as_str())
+                    .unwrap_or_default(),
+                tg.health_check_port().unwrap_or_default(),
+                tg.health_check_path().unwrap_or_default()
+            ),
+        })
+        .collect();
+    target_groups.sort_by(|a, b| a.arn.cmp(&b.arn));
+
+    let description = ElbV2Description {
+        scheme: lb.scheme().map(|scheme| scheme.as_str().to_string()),
+        ip_address_type: lb.ip_address_type().map(|t| t.as_str().to_string()),
+        subnets,
+        security_groups,
+        target_groups,
+    };
+    serde_json::to_string(&description).unwrap_or_default()
+}
+
+pub async fn process_account(
+    account_id: String,
+    credentials: SharedCredentialsProvider,
+    conf: Arc<AppConfig>,
+    aws_account: AwsAccount,
+) -> (Vec<ElbV2Data>, Vec<Error>) {
+    let regions = utils::parse_regions_arg(&aws_account.regions);
 
     let mut tasks = Vec::new();
-    let mut inactive_elbv2_data: Vec<ElbV2Data> = vec![];
+    let mut elbv2_data: Vec<ElbV2Data> = vec![];
+    let mut errors: Vec<Error> = vec![];
 
     for region in regions {
         let elbv2_task = tokio::spawn(process_region(
-            region,
+            region.clone(),
             credentials.clone(),
-            days,
-            vpc_ids.clone(),
+            Arc::clone(&conf),
+            aws_account.clone(),
         ));
-        tasks.push(elbv2_task);
+        tasks.push((region, elbv2_task));
     }
 
-    for task in tasks {
-        let elbv2 = task.await.unwrap();
+    for (region, task) in tasks {
+        match task.await {
+            Ok((mut elbv2, mut region_errors)) => {
+                elbv2_data.append(&mut elbv2);
+                errors.append(&mut region_errors);
+            }
+            Err(e) => errors.push(Error::Region {
+                region: region.to_string(),
+                message: format!("error discovering ELBv2s: {}", e),
+            }),
+        }
+    }
 
-        let mut elbv2 = elbv2
-            .into_iter()
-            .filter(|elbv2| elbv2.state == LoadBalancerState::Inactive)
-            .collect::<Vec<ElbV2Data>>();
+    let inactive_elbv2_data = elbv2_data
+        .iter()
+        .filter(|elbv2| elbv2.state == LoadBalancerState::Inactive)
+        .cloned()
+        .collect::<Vec<ElbV2Data>>();
+    let undetermined_elbv2_data = elbv2_data
+        .iter()
+        .filter(|elbv2| elbv2.state.is_undetermined())
+        .cloned()
+        .collect::<Vec<ElbV2Data>>();
+    let skipped_elbv2_data = elbv2_data
+        .iter()
+        .filter(|elbv2| elbv2.state.is_skipped())
+        .cloned()
+        .collect::<Vec<ElbV2Data>>();
+    let filtered_elbv2_data = elbv2_data
+        .iter()
+        .filter(|elbv2| elbv2.state.is_filtered())
+        .cloned()
+        .collect::<Vec<ElbV2Data>>();
 
-        inactive_elbv2_data.append(&mut elbv2);
+    println!(
+        "Account {}: {} ELBv2s, {} inactive, {} undetermined, {} skipped, {} filtered",
+        account_id,
+        elbv2_data.len(),
+        inactive_elbv2_data.len(),
+        undetermined_elbv2_data.len(),
+        skipped_elbv2_data.len(),
+        filtered_elbv2_data.len()
+    );
+    for elbv2 in undetermined_elbv2_data.iter() {
+        println!("Undetermined ELBv2: {} {}", elbv2.arn, elbv2.state);
     }
 
-    match run_option {
-        RunOption::List => {
-            let mut to_write: Vec<String> = vec![];
-            to_write.push("arn,state,region,vpc_id".to_string());
-            for elbv2_data in inactive_elbv2_data.iter_mut() {
-                let line = elbv2_data.to_string();
-                to_write.push(line);
-            }
+    let file_name = format!("outputs/{}_inactive_elbv2s.csv", &account_id);
+    write_report(file_name.as_str(), &inactive_elbv2_data);
 
-            let file_name = format!("outputs/{}_inactive_elbv2s.csv", &account_id);
-            if let Err(e) = utils::write_csv(file_name.as_str(), to_write) {
-                println!("Error writing to csv file! {}", e);
-            }
-        }
-        RunOption::Delete => {
-            let mut tasks = Vec::new();
+    let file_name = format!("outputs/{}_undetermined_elbv2s.csv", &account_id);
+    write_report(file_name.as_str(), &undetermined_elbv2_data);
 
-            let elbv2_task = tokio::spawn(process_elbv2(inactive_elbv2_data));
+    let file_name = format!("outputs/{}_skipped_elbv2s.csv", &account_id);
+    write_report(file_name.as_str(), &skipped_elbv2_data);
 
-            tasks.push(elbv2_task);
+    let file_name = format!("outputs/{}_filtered_elbv2s.csv", &account_id);
+    write_report(file_name.as_str(), &filtered_elbv2_data);
 
-            futures::future::join_all(tasks).await;
-        }
-        RunOption::Unknown => {
-            panic!("Run option invalid!");
-        }
+    (elbv2_data, errors)
+}
+
+fn write_report(file_name: &str, elbv2_data: &[ElbV2Data]) {
+    let mut to_write: Vec<String> = vec![];
+    to_write.push(
+        "arn,state,region,vpc_id,created_time,last_active_at,idle_days,tags,type,listener_actions,target_types,deletion_protection".to_string(),
+    );
+    for elbv2 in elbv2_data.iter() {
+        to_write.push(elbv2.to_csv());
+    }
+
+    if let Err(e) = utils::write_csv(file_name, to_write) {
+        println!("Error writing to csv file! {}", e);
     }
 }
 
 pub async fn process_region(
     region: Region,
-    credentials: Credentials,
-    days: i64,
-    vpc_ids: HashMap<String, bool>,
-) -> Vec<ElbV2Data> {
+    credentials: SharedCredentialsProvider,
+    conf: Arc<AppConfig>,
+    aws_account: AwsAccount,
+) -> (Vec<ElbV2Data>, Vec<Error>) {
     let config = aws_config::from_env()
+        .retry_config(retry::sdk_retry_config())
         .credentials_provider(credentials)
-        .region(region)
+        .region(region.clone())
         .load()
         .await;
 
     let elbv2_client = ELBv2Client::new(&config);
+    let ec2_client = Ec2Client::new(&config);
     let cw_client = CloudWatchClient::new(&config);
 
-    let elbv2_lbs = get_elbv2_load_balancers(&elbv2_client).await;
-    let elbv2_data: Arc<Mutex<Vec<ElbV2Data>>> = Arc::new(Mutex::new(vec![]));
-    let sem = Arc::new(Semaphore::new(5));
+    let elbv2_lbs = match get_elbv2_load_balancers(&elbv2_client).await {
+        Ok(elbv2_lbs) => elbv2_lbs,
+        Err(e) => {
+            let error = Error::Region {
+                region: region.to_string(),
+                message: format!("error listing ELBv2s: {}", e),
+            };
+            return (vec![], vec![error]);
+        }
+    };
+    let target_groups = get_elbv2_target_groups(&elbv2_client).await;
+    let arns = elbv2_lbs
+        .iter()
+        .filter_map(|lb| lb.load_balancer_arn())
+        .map(|arn| arn.to_string())
+        .collect::<Vec<String>>();
+    let tags = get_elbv2_tags(&elbv2_client, &arns).await;
+
+    let vpc_tags =
+        if aws_account.include_vpc_tags.is_empty() && aws_account.exclude_vpc_tags.is_empty() {
+            Ok(HashMap::new())
+        } else {
+            get_vpc_tags(&ec2_client).await
+        };
 
-    let mut tasks = Vec::new();
+    // Load balancers that are outside the selected VPCs, excluded by name,
+    // ARN or tags, or are too new are decided without reading their metrics.
+    let mut prejudged: HashMap<String, LoadBalancerState> = HashMap::new();
+    let mut requests: Vec<MetricRequest> = vec![];
+    let no_tags = HashMap::new();
+    for lb in elbv2_lbs.iter() {
+        let arn = match lb.load_balancer_arn() {
+            Some(arn) => arn,
+            None => continue,
+        };
+        let created_time = utils::to_chrono_time(lb.created_time());
+        let lb_tags = tags.as_ref().map(|tags| tags.get(arn).unwrap_or(&no_tags));
+        let vpc_id = lb.vpc_id().unwrap_or_default();
+        let lb_vpc_tags = vpc_tags
+            .as_ref()
+            .map(|tags| tags.get(vpc_id).unwrap_or(&no_tags));
+        let state = filters::vpc_filter_state(vpc_id, lb_vpc_tags, &aws_account).or_else(|| {
+            filters::state_before_metrics(
+                lb.load_balancer_name().unwrap_or_default(),
+                Some(arn),
+                &created_time,
+                lb_tags,
+                &conf,
+                &aws_account,
+            )
+        });
+        if let Some(state) = state {
+            prejudged.insert(arn.to_string(), state);
+            continue;
+        }
+        if let Ok(target_groups) = &target_groups {
+            let lb_target_groups = target_groups.get(arn).map(|tgs| tgs.as_slice());
+            requests.append(&mut elbv2_metric_requests(
+                arn,
+                lb_target_groups.unwrap_or_default(),
+                &conf.scoring,
+            ));
+        }
+    }
+
+    println!(
+        "Fetching {} metrics for {} ELBv2s in {}",
+        requests.len(),
+        elbv2_lbs.len(),
+        region
+    );
+    let stats = get_metric_stats(&cw_client, requests, conf.days).await;
+
+    let has_gateways = elbv2_lbs
+        .iter()
+        .any(|lb| lb.r#type() == Some(&LoadBalancerTypeEnum::Gateway));
+    let gwlb_endpoints = if has_gateways {
+        get_gwlb_endpoint_counts(&ec2_client).await
+    } else {
+        Ok(HashMap::new())
+    };
+
+    let mut elbv2_data: Vec<ElbV2Data> = vec![];
+    let mut errors: Vec<Error> = vec![];
+
+    // The description of every load balancer and its target groups, hashed
+    // once listeners and targets are known.
+    let mut descriptions: HashMap<String, String> = HashMap::new();
 
     for lb in elbv2_lbs {
-        let client = elbv2_client.clone();
-        let cw_client = cw_client.clone();
-        let sem = Arc::clone(&sem);
-
-        let arn = lb.load_balancer_arn().unwrap().to_string();
-        let vpc_id = lb.vpc_id().unwrap().to_string();
-        let vpc_ids = vpc_ids.clone();
-        let region_string = utils::extract_region_from_elbv2_arn(&arn).unwrap();
+        let arn = match lb.load_balancer_arn() {
+            Some(arn) => arn.to_string(),
+            None => {
+                errors.push(Error::Region {
+                    region: region.to_string(),
+                    message: "ELBv2 without an ARN".to_string(),
+                });
+                continue;
+            }
+        };
+        let vpc_id = match lb.vpc_id() {
+            Some(vpc_id) => vpc_id.to_string(),
+            None => {
+                errors.push(Error::LoadBalancer {
+                    id: arn,
+                    message: "ELBv2 has no VPC".to_string(),
+                });
+                continue;
+            }
+        };
+        let region_string = match utils::extract_region_from_elbv2_arn(&arn) {
+            Some(region_string) => region_string,
+            None => {
+                errors.push(Error::LoadBalancer {
+                    id: arn,
+                    message: "no region in ARN".to_string(),
+                });
+                continue;
+            }
+        };
         let region = Region::new(region_string);
-        let elbv2_data = Arc::clone(&elbv2_data);
 
-        let task = async move {
-            println!("Processing ELBv2: {}", arn);
-            let _perm = sem.acquire_owned().await;
-            let state = get_elbv2_lb_state(arn.to_string(), &client, &cw_client, days).await;
-            if let Some(state) = state {
-                let mut elbv2_data = elbv2_data.lock().unwrap();
-                if vpc_ids.len() > 0 && vpc_ids.contains_key(vpc_id.as_str()) {
-                    elbv2_data.push(ElbV2Data::new(arn.as_str(), state, region, vpc_id));
-                } else {
-                    elbv2_data.push(ElbV2Data::new(arn.as_str(), state, region, vpc_id));
-                }
-            }
+        let created_time = utils::to_chrono_time(lb.created_time());
+
+        println!("Processing ELBv2: {}", arn);
+        let judged = !prejudged.contains_key(&arn);
+        let state = match (prejudged.remove(&arn), &target_groups) {
+            (Some(state), _) => state,
+            (None, Ok(_)) => get_elbv2_lb_state(&arn, &stats, &conf.scoring),
+            (None, Err(e)) => LoadBalancerState::Errored(e.clone()),
         };
-        tasks.push(task);
+        let lb_type = lb.r#type().map(|t| t.as_str()).unwrap_or_default();
+        let state = if state == LoadBalancerState::Inactive && lb_type == "gateway" {
+            gwlb_state(&arn, &gwlb_endpoints)
+        } else {
+            state
+        };
+        let mut elbv2 = ElbV2Data::new(arn.as_str(), state, region, vpc_id, created_time, lb_type);
+        if let Ok(tags) = &tags {
+            elbv2.tags = tags.get(&arn).cloned().unwrap_or_default();
+            elbv2.tags_known = true;
+        }
+        let lb_target_groups = target_groups.as_ref().ok().and_then(|tgs| tgs.get(&arn));
+        descriptions.insert(
+            arn.clone(),
+            elbv2_description(&lb, lb_target_groups.map(|tgs| tgs.as_slice())),
+        );
+        if judged {
+            elbv2.last_active_at = get_elbv2_last_active_at(&arn, &stats, &conf.scoring);
+            elbv2.evidence = get_elbv2_evidence(&arn, &stats, &conf.scoring);
+            elbv2.idle_days =
+                utils::idle_days(&elbv2.last_active_at, &elbv2.created_time, conf.days);
+        }
+
+        if elbv2.state.is_undetermined() {
+            errors.push(Error::LoadBalancer {
+                id: elbv2.arn.clone(),
+                message: elbv2.state.to_string(),
+            });
+        }
+        elbv2_data.push(elbv2);
     }
 
-    let mut futures = Vec::new();
-    for task in tasks {
-        futures.push(task);
+    // Listener actions, target types and deletion protection are only needed
+    // to review load balancers that may be deleted, so active, filtered and
+    // skipped ones are not described.
+    let mut describes = vec![];
+    for (index, elbv2) in elbv2_data.iter().enumerate() {
+        if elbv2.state == LoadBalancerState::Active
+            || elbv2.state.is_filtered()
+            || elbv2.state.is_skipped()
+        {
+            continue;
+        }
+        let client = &elbv2_client;
+        let arn = elbv2.arn.clone();
+        let lb_target_groups = target_groups
+            .as_ref()
+            .ok()
+            .map(|tgs| tgs.get(&arn).map(|tgs| tgs.as_slice()).unwrap_or_default());
+        describes.push(async move {
+            let listener_actions = get_elbv2_listener_actions(client, &arn).await;
+            let deletion_protection = get_elbv2_deletion_protection(client, &arn).await;
+            let target_types = match lb_target_groups {
+                Some(lb_target_groups) => {
+                    Some(get_elbv2_target_types(client, lb_target_groups).await)
+                }
+                None => None,
+            };
+            (index, listener_actions, deletion_protection, target_types)
+        });
+    }
+    let described: Vec<_> = stream::iter(describes)
+        .buffered(MAX_CONCURRENT_DESCRIBES)
+        .collect()
+        .await;
+
+    for (index, listener_actions, deletion_protection, target_types) in described {
+        let elbv2 = &mut elbv2_data[index];
+        match listener_actions {
+            Ok(listener_actions) => elbv2.listener_actions = listener_actions,
+            Err(e) => errors.push(Error::LoadBalancer {
+                id: elbv2.arn.clone(),
+                message: format!("error describing listeners: {}", e),
+            }),
+        }
+        match deletion_protection {
+            Ok(enabled) => elbv2.deletion_protection = Some(enabled),
+            Err(e) => errors.push(Error::LoadBalancer {
+                id: elbv2.arn.clone(),
+                message: format!("error describing attributes: {}", e),
+            }),
+        }
+        // Protected load balancers would be refused at deletion, so they are
+        // kept out of the limits, the confirmation and the plan.
+        if elbv2.state == LoadBalancerState::Inactive
+            && elbv2.deletion_protection == Some(true)
+            && !conf.disable_deletion_protection
+        {
+            elbv2.state = LoadBalancerState::Skipped("deletion protection enabled".to_string());
+        }
+        match target_types {
+            Some(Ok(target_types)) => elbv2.target_types = target_types,
+            Some(Err(e)) => errors.push(Error::LoadBalancer {
+                id: elbv2.arn.clone(),
+                message: format!("error describing targets: {}", e),
+            }),
+            None => (),
+        }
     }
-    futures::future::join_all(futures).await;
 
-    let elbv2_data = elbv2_data.lock().unwrap();
+    for elbv2 in elbv2_data.iter_mut() {
+        elbv2.config_hash = utils::config_hash(&[
+            descriptions.remove(&elbv2.arn).unwrap_or_default(),
+            elbv2.listener_actions.clone(),
+            elbv2.target_types.clone(),
+            match elbv2.deletion_protection {
+                Some(enabled) => enabled.to_string(),
+                None => String::new(),
+            },
+            utils::format_tags(&elbv2.tags),
+        ]);
+    }
 
-    elbv2_data.to_vec()
+    (elbv2_data, errors)
 }
 
-pub async fn process_elbv2(elbv2s: Vec<ElbV2Data>) -> Vec<DeleteOutput> {
-    let deletion_results: Arc<Mutex<Vec<DeleteOutput>>> = Arc::new(Mutex::new(vec![]));
+pub async fn process_elbv2(
+    elbv2s: Vec<ElbV2Data>,
+    credentials: SharedCredentialsProvider,
+    account_id: String,
+    archive_dir: String,
+    disable_deletion_protection: bool,
+) -> Vec<Result<String, Error>> {
+    if let Err(e) = sts::verify_account(&credentials, &account_id).await {
+        println!("Skipping ELBv2 deletion in account {}: {}", account_id, e);
+        return vec![Err(Error::Account {
+            account_id,
+            message: format!("ELBv2s not deleted: {}", e),
+        })];
+    }
+
+    let deletion_results: Arc<Mutex<Vec<Result<String, Error>>>> = Arc::new(Mutex::new(vec![]));
     let mut tasks = Vec::new();
 
     for elbv2 in elbv2s {
+        if elbv2.state != LoadBalancerState::Inactive {
+            println!("Skipping ELBv2 deletion: {} is {}", elbv2.arn, elbv2.state);
+            continue;
+        }
+
         let region = elbv2.region;
         let arn = elbv2.arn;
 
-        let config = aws_config::from_env().region(region).load().await;
+        let config = aws_config::from_env()
+            .retry_config(retry::sdk_retry_config())
+            .credentials_provider(credentials.clone())
+            .region(region.clone())
+            .load()
+            .await;
         let client = ELBv2Client::new(&config);
 
         let deletion_results = Arc::clone(&deletion_results);
+        let account_id = account_id.clone();
+        let archive_dir = archive_dir.clone();
 
         let task = async move {
+            let fail = |message: String| {
+                let mut deletion_results = deletion_results.lock().unwrap();
+                deletion_results.push(Err(Error::LoadBalancer {
+                    id: arn.clone(),
+                    message,
+                }));
+            };
+
             println!("Processing ELBv2 deletion: {}", arn);
-            let res = delete_elbv2(&arn, &client).await;
-            let mut deletion_results = deletion_results.lock().unwrap();
-            deletion_results.push(res);
+            // Protection is checked before the backup, so that refused load
+            // balancers leave no snapshot behind.
+            let protected = match get_elbv2_deletion_protection(&client, &arn).await {
+                Ok(false) => false,
+                Ok(true) if !disable_deletion_protection => {
+                    println!(
+                        "Skipping ELBv2 deletion: {} has deletion protection enabled",
+                        arn
+                    );
+                    fail("not deleted, deletion protection enabled".to_string());
+                    return;
+                }
+                Ok(true) => true,
+                Err(e) => {
+                    println!(
+                        "Skipping ELBv2 deletion: could not read attributes of {}: {}",
+                        arn, e
+                    );
+                    fail(format!("not deleted, could not read attributes: {}", e));
+                    return;
+                }
+            };
+
+            let snapshot = take_elbv2_snapshot(&client, &account_id, region.as_ref(), &arn)
+                .await
+                .and_then(|snapshot| write_snapshot(&archive_dir, &snapshot));
+            match snapshot {
+                Ok(file_name) => println!("Backed up ELBv2 {} to {}", arn, file_name),
+                Err(e) => {
+                    println!("Skipping ELBv2 deletion: could not back up {}: {}", arn, e);
+                    fail(format!("not deleted, could not back up: {}", e));
+                    return;
+                }
+            }
+
+            if protected {
+                if let Err(e) = set_elbv2_deletion_protection(&client, &arn, false).await {
+                    println!(
+                        "Skipping ELBv2 deletion: could not disable deletion protection of {}: {}",
+                        arn, e
+                    );
+                    fail(format!(
+                        "not deleted, could not disable deletion protection: {}",
+                        e
+                    ));
+                    return;
+                }
+                println!("Disabled deletion protection of {}", arn);
+            }
+
+            match delete_elbv2(&arn, &client).await {
+                Ok(_) => {
+                    let mut deletion_results = deletion_results.lock().unwrap();
+                    deletion_results.push(Ok(arn.clone()));
+                }
+                Err(e) if protected => {
+                    println!("Error deleting ELBv2 {}: {}", arn, e);
+                    match set_elbv2_deletion_protection(&client, &arn, true).await {
+                        Ok(()) => {
+                            println!("Enabled deletion protection of {} again", arn);
+                            fail(format!(
+                                "error deleting: {}, deletion protection enabled again",
+                                e
+                            ));
+                        }
+                        Err(protection_error) => {
+                            println!(
+                                "Error enabling deletion protection of {} again: {}",
+                                arn, protection_error
+                            );
+                            fail(format!(
+                                "error deleting: {}, deletion protection left disabled: {}",
+                                e, protection_error
+                            ));
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("Error deleting ELBv2 {}: {}", arn, e);
+                    fail(format!("error deleting: {}", e));
+                }
+            }
         };
 
         tasks.push(task);
@@ -243,17 +699,19 @@ pub async fn process_elbv2(elbv2s: Vec<ElbV2Data>) -> Vec<DeleteOutput> {
     deletion_results.to_vec()
 }
 
-async fn get_elbv2_load_balancers(client: &ELBv2Client) -> Vec<LoadBalancerV2> {
+async fn get_elbv2_load_balancers(client: &ELBv2Client) -> Result<Vec<LoadBalancerV2>, String> {
     let mut lbs = Vec::new();
     let mut next_marker = None;
 
     loop {
-        let resp = client
-            .describe_load_balancers()
-            .set_marker(next_marker)
-            .send()
-            .await
-            .unwrap();
+        let resp = retry::send("ELBv2 DescribeLoadBalancers", || {
+            client
+                .describe_load_balancers()
+                .set_marker(next_marker.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| e.to_string())?;
 
         lbs.extend(resp.load_balancers.unwrap_or_default());
         next_marker = resp.next_marker;
@@ -261,78 +719,457 @@ async fn get_elbv2_load_balancers(client: &ELBv2Client) -> Vec<LoadBalancerV2> {
             break;
         }
     }
-    lbs
+    Ok(lbs)
 }
 
-async fn get_elbv2_lb_state(
-    arn: String,
-    elbv2_client: &ELBv2Client,
-    cw_client: &CloudWatchClient,
-    days: i64,
-) -> Option<LoadBalancerState> {
-    let target_groups = elbv2_client
-        .describe_target_groups()
-        .load_balancer_arn(arn.clone())
-        .send()
+/// Returns every target group in the region keyed by the ARN of each load
+/// balancer it is attached to.
+async fn get_elbv2_target_groups(
+    client: &ELBv2Client,
+) -> Result<HashMap<String, Vec<TargetGroup>>, String> {
+    let mut tgs: HashMap<String, Vec<TargetGroup>> = HashMap::new();
+    let mut next_marker = None;
+
+    loop {
+        let resp = retry::send("ELBv2 DescribeTargetGroups", || {
+            client
+                .describe_target_groups()
+                .set_marker(next_marker.clone())
+                .send()
+        })
         .await
-        .unwrap()
-        .target_groups
-        .unwrap_or_default();
+        .map_err(|e| format!("error describing target groups: {}", e))?;
+
+        for tg in resp.target_groups.unwrap_or_default() {
+            for lb_arn in tg.load_balancer_arns().unwrap_or_default() {
+                tgs.entry(lb_arn.to_string()).or_default().push(tg.clone());
+            }
+        }
+        next_marker = resp.next_marker;
+        if next_marker.is_none() {
+            break;
+        }
+    }
+    Ok(tgs)
+}
+
+/// Summarizes the default action of every listener, e.g.
+/// `HTTP:80 redirect; HTTPS:443 fixed-response`.
+async fn get_elbv2_listener_actions(client: &ELBv2Client, arn: &str) -> Result<String, String> {
+    let mut listener_actions: Vec<String> = vec![];
+    let mut next_marker = None;
+
+    loop {
+        let resp = retry::send("ELBv2 DescribeListeners", || {
+            client
+                .describe_listeners()
+                .load_balancer_arn(arn)
+                .set_marker(next_marker.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for listener in resp.listeners().unwrap_or_default() {
+            let actions = listener
+                .default_actions()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|action| action.r#type())
+                .map(|action_type| action_type.as_str())
+                .collect::<Vec<&str>>();
+            listener_actions.push(format!(
+                "{}:{} {}",
+                listener.protocol().map(|p| p.as_str()).unwrap_or_default(),
+                listener.port().unwrap_or_default(),
+                actions.join("+")
+            ));
+        }
+        next_marker = resp.next_marker().map(|marker| marker.to_string());
+        if next_marker.is_none() {
+            break;
+        }
+    }
+
+    Ok(listener_actions.join("; "))
+}
+
+/// Returns the tags of every load balancer keyed by ARN. DescribeTags takes
+/// at most 20 resources per call.
+pub async fn get_elbv2_tags(
+    client: &ELBv2Client,
+    arns: &[String],
+) -> Result<HashMap<String, HashMap<String, String>>, String> {
+    let mut tags: HashMap<String, HashMap<String, String>> = HashMap::new();
+
+    for arns in arns.chunks(20) {
+        let resp = retry::send("ELBv2 DescribeTags", || {
+            client
+                .describe_tags()
+                .set_resource_arns(Some(arns.to_vec()))
+                .send()
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+        for description in resp.tag_descriptions().unwrap_or_default() {
+            let lb_tags = description
+                .tags()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|tag| {
+                    tag.key()
+                        .map(|key| (key.to_string(), tag.value().unwrap_or_default().to_string()))
+                })
+                .collect();
+            if let Some(arn) = description.resource_arn() {
+                tags.insert(arn.to_string(), lb_tags);
+            }
+        }
+    }
+
+    Ok(tags)
+}
+
+fn target_type_of(tg: &TargetGroup) -> &str {
+    tg.target_type().map(|t| t.as_str()).unwrap_or_default()
+}
 
-    let lb_value = utils::extract_id_from_lb_arn(&arn).unwrap();
-    let lb_namespace = utils::extract_namespace_from_lb_type(&arn).unwrap();
-    let mut active = false;
+/// Summarizes the target type of every target group, e.g.
+/// `web:instance; api:lambda; onprem:ip(external)`. IP target groups whose
+/// targets live outside the VPC (peered or on-premises addresses, registered
+/// with the `all` availability zone) are marked as external.
+async fn get_elbv2_target_types(
+    client: &ELBv2Client,
+    target_groups: &[TargetGroup],
+) -> Result<String, String> {
+    let mut target_types: Vec<String> = vec![];
 
     for tg in target_groups {
-        let tg_arn = tg.target_group_arn().unwrap();
-        let tg_value = utils::extract_id_from_tg_arn(&tg_arn).unwrap();
-
-        let dimensions = vec![
-            Dimension::builder()
-                .name("LoadBalancer")
-                .value(lb_value.clone())
-                .build(),
-            Dimension::builder()
-                .name("TargetGroup")
-                .value(tg_value.clone())
-                .build(),
-        ];
-
-        let metric = Metric::builder()
-            .namespace(&lb_namespace)
-            .metric_name("HealthyHostCount")
-            .set_dimensions(Some(dimensions))
+        let mut target_type = target_type_of(tg).to_string();
+
+        if target_type == "ip" {
+            let resp = retry::send("ELBv2 DescribeTargetHealth", || {
+                client
+                    .describe_target_health()
+                    .set_target_group_arn(tg.target_group_arn().map(|arn| arn.to_string()))
+                    .send()
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+            let external = resp
+                .target_health_descriptions()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|description| description.target())
+                .any(|target| target.availability_zone() == Some("all"));
+            if external {
+                target_type = "ip(external)".to_string();
+            }
+        }
+
+        target_types.push(format!(
+            "{}:{}",
+            tg.target_group_name().unwrap_or_default(),
+            target_type
+        ));
+    }
+
+    Ok(target_types.join("; "))
+}
+
+fn elbv2_metric_key(arn: &str, rule: &MetricRule) -> String {
+    format!(
+        "{}|{}|{}|{}|{}",
+        arn,
+        rule.metric_name,
+        rule.stat,
+        rule.dimension,
+        rule.target_types.join(",")
+    )
+}
+
+fn elbv2_metric_requests(
+    arn: &str,
+    target_groups: &[TargetGroup],
+    scoring: &ScoringConfig,
+) -> Vec<MetricRequest> {
+    let (lb_value, lb_namespace) = match (
+        utils::extract_id_from_lb_arn(arn),
+        utils::extract_namespace_from_lb_type(arn),
+    ) {
+        (Some(lb_value), Some(lb_namespace)) => (lb_value, lb_namespace),
+        _ => return vec![],
+    };
+    let rules = scoring::rules_for_namespace(&lb_namespace, scoring).unwrap_or_default();
+    let mut requests = vec![];
+
+    for rule in rules {
+        let lb_dimension = Dimension::builder()
+            .name("LoadBalancer")
+            .value(lb_value.clone())
             .build();
 
-        let stats = get_metric_stats(&cw_client, metric, days).await;
+        let dimension_sets = match rule.dimension {
+            MetricDimension::LoadBalancer => vec![vec![lb_dimension]],
+            MetricDimension::TargetGroup => target_groups
+                .iter()
+                .filter(|tg| rule.applies_to_target_type(target_type_of(tg)))
+                .filter_map(|tg| tg.target_group_arn())
+                .filter_map(utils::extract_id_from_tg_arn)
+                .map(|tg_value| {
+                    vec![
+                        lb_dimension.clone(),
+                        Dimension::builder()
+                            .name("TargetGroup")
+                            .value(tg_value)
+                            .build(),
+                    ]
+                })
+                .collect(),
+        };
+
+        let metrics = dimension_sets
+            .into_iter()
+            .map(|dimensions| {
+                Metric::builder()
+                    .namespace(&lb_namespace)
+                    .metric_name(&rule.metric_name)
+                    .set_dimensions(Some(dimensions))
+                    .build()
+            })
+            .collect();
+
+        requests.push(MetricRequest::new(
+            &elbv2_metric_key(arn, rule),
+            metrics,
+            &rule.stat,
+        ));
+    }
+
+    requests
+}
+
+/// An idle Gateway Load Balancer is only deletable once no endpoints are
+/// connected to its endpoint service, otherwise the route tables sending
+/// traffic to those endpoints would blackhole it.
+fn gwlb_state(
+    arn: &str,
+    gwlb_endpoints: &Result<HashMap<String, usize>, String>,
+) -> LoadBalancerState {
+    match gwlb_endpoints {
+        Ok(gwlb_endpoints) => match gwlb_endpoints.get(arn) {
+            Some(count) if *count > 0 => LoadBalancerState::Skipped(format!(
+                "{} Gateway Load Balancer endpoints still connected",
+                count
+            )),
+            _ => LoadBalancerState::Inactive,
+        },
+        Err(e) => LoadBalancerState::Unknown(e.clone()),
+    }
+}
+
+fn get_elbv2_lb_state(
+    arn: &str,
+    stats: &HashMap<String, Result<MetricDataResult, String>>,
+    scoring: &ScoringConfig,
+) -> LoadBalancerState {
+    let rules = match utils::extract_namespace_from_lb_type(arn)
+        .and_then(|lb_namespace| scoring::rules_for_namespace(&lb_namespace, scoring))
+    {
+        Some(rules) => rules,
+        None => return LoadBalancerState::Errored("unsupported load balancer type".to_string()),
+    };
+    let mut score = 0.0;
+    let mut failures: Vec<String> = vec![];
 
-        match stats {
-            Some(stats) => {
+    for rule in rules {
+        match stats.get(&elbv2_metric_key(arn, rule)) {
+            Some(Ok(stats)) => {
                 let values = stats.values().unwrap_or(&[]);
-                let sum: f64 = values.iter().sum();
-                if sum > 0.0 {
-                    active = true;
-                    break;
-                }
+                score += scoring::score_metric(rule, values);
             }
+            Some(Err(e)) => failures.push(format!("{}: {}", rule.metric_name, e)),
             None => (),
         }
     }
 
-    if active {
-        Some(LoadBalancerState::Active)
-    } else {
-        Some(LoadBalancerState::Inactive)
-    }
+    scoring::state_from_score(score, &failures, scoring)
 }
 
-async fn delete_elbv2(arn: &str, client: &ELBv2Client) -> DeleteOutput {
-    let out = client
-        .delete_load_balancer()
-        .load_balancer_arn(arn)
-        .send()
-        .await
-        .unwrap();
+fn get_elbv2_evidence(
+    arn: &str,
+    stats: &HashMap<String, Result<MetricDataResult, String>>,
+    scoring: &ScoringConfig,
+) -> Vec<String> {
+    let rules = utils::extract_namespace_from_lb_type(arn)
+        .and_then(|lb_namespace| scoring::rules_for_namespace(&lb_namespace, scoring))
+        .unwrap_or_default();
+
+    rules
+        .iter()
+        .filter_map(|rule| match stats.get(&elbv2_metric_key(arn, rule)) {
+            Some(Ok(stats)) => Some(scoring::evidence(rule, stats.values().unwrap_or(&[]))),
+            _ => None,
+        })
+        .collect()
+}
+
+fn get_elbv2_last_active_at(
+    arn: &str,
+    stats: &HashMap<String, Result<MetricDataResult, String>>,
+    scoring: &ScoringConfig,
+) -> Option<DateTime<Utc>> {
+    let rules = utils::extract_namespace_from_lb_type(arn)
+        .and_then(|lb_namespace| scoring::rules_for_namespace(&lb_namespace, scoring))
+        .unwrap_or_default();
+
+    rules
+        .iter()
+        .filter_map(|rule| match stats.get(&elbv2_metric_key(arn, rule)) {
+            Some(Ok(stats)) => scoring::last_active_at(rule, stats, scoring),
+            _ => None,
+        })
+        .max()
+}
+
+async fn get_elbv2_deletion_protection(client: &ELBv2Client, arn: &str) -> Result<bool, String> {
+    let resp = retry::send("ELBv2 DescribeLoadBalancerAttributes", || {
+        client
+            .describe_load_balancer_attributes()
+            .load_balancer_arn(arn)
+            .send()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let enabled = resp
+        .attributes()
+        .unwrap_or_default()
+        .iter()
+        .any(|attribute| {
+            attribute.key() == Some("deletion_protection.enabled")
+                && attribute.value() == Some("true")
+        });
+    Ok(enabled)
+}
+
+async fn set_elbv2_deletion_protection(
+    client: &ELBv2Client,
+    arn: &str,
+    enabled: bool,
+) -> Result<(), String> {
+    retry::send("ELBv2 ModifyLoadBalancerAttributes", || {
+        client
+            .modify_load_balancer_attributes()
+            .load_balancer_arn(arn)
+            .attributes(
+                LoadBalancerAttribute::builder()
+                    .key("deletion_protection.enabled")
+                    .value(enabled.to_string())
+                    .build(),
+            )
+            .send()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub async fn add_elbv2_tags(
+    client: &ELBv2Client,
+    arn: &str,
+    tags: &HashMap<String, String>,
+) -> Result<(), String> {
+    let tags = tags
+        .iter()
+        .map(|(key, value)| Tag::builder().key(key).value(value).build())
+        .collect::<Vec<Tag>>();
+    retry::send("ELBv2 AddTags", || {
+        client
+            .add_tags()
+            .resource_arns(arn)
+            .set_tags(Some(tags.clone()))
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error tagging {}: {}", arn, e))?;
+    Ok(())
+}
+
+pub async fn remove_elbv2_tags(
+    client: &ELBv2Client,
+    arn: &str,
+    keys: &[&str],
+) -> Result<(), String> {
+    retry::send("ELBv2 RemoveTags", || {
+        client
+            .remove_tags()
+            .resource_arns(arn)
+            .set_tag_keys(Some(keys.iter().map(|key| key.to_string()).collect()))
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error untagging {}: {}", arn, e))?;
+    Ok(())
+}
+
+async fn delete_elbv2(arn: &str, client: &ELBv2Client) -> Result<DeleteOutput, String> {
+    let out = retry::send("ELBv2 DeleteLoadBalancer", || {
+        client.delete_load_balancer().load_balancer_arn(arn).send()
+    })
+    .await
+    .map_err(|e| e.to_string())?;
     println!("Deleted ELBv2 Load Balancer: {:?}", arn);
-    out
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use aws_sdk_elasticloadbalancingv2::model::{
+        AvailabilityZone, LoadBalancerSchemeEnum, ProtocolEnum, TargetTypeEnum,
+    };
+
+    fn lb(subnets: &[&str]) -> LoadBalancerV2 {
+        subnets
+            .iter()
+            .fold(
+                LoadBalancerV2::builder()
+                    .scheme(LoadBalancerSchemeEnum::Internal)
+                    .security_groups("sg-2")
+                    .security_groups("sg-1"),
+                |builder, subnet| {
+                    builder
+                        .availability_zones(AvailabilityZone::builder().subnet_id(*subnet).build())
+                },
+            )
+            .build()
+    }
+
+    #[test]
+    fn describes_the_configuration_as_json() {
+        let target_group = TargetGroup::builder()
+            .target_group_arn("arn:tg/web")
+            .protocol(ProtocolEnum::Http)
+            .port(8080)
+            .target_type(TargetTypeEnum::Ip)
+            .health_check_protocol(ProtocolEnum::Http)
+            .health_check_port("traffic-port")
+            .health_check_path("/health")
+            .build();
+        assert_eq!(
+            elbv2_description(&lb(&["subnet-b", "subnet-a"]), Some(&[target_group])),
+            r#"{"scheme":"internal","ip_address_type":null,"subnets":["subnet-a","subnet-b"],"security_groups":["sg-1","sg-2"],"target_groups":[{"arn":"arn:tg/web","protocol":"HTTP","port":8080,"target_type":"ip","health_check":"HTTP traffic-port /health"}]}"#
+        );
+    }
+
+    #[test]
+    fn ignores_the_order_aws_lists_things_in() {
+        assert_eq!(
+            elbv2_description(&lb(&["subnet-a", "subnet-b"]), None),
+            elbv2_description(&lb(&["subnet-b", "subnet-a"]), None)
+        );
+    }
 }
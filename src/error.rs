@@ -0,0 +1,93 @@
+use std::fmt;
+
+/// Everything that can go wrong in a run. Config, file and limit errors are
+/// fatal and stop the run before anything is deleted. The others concern a
+/// single account, region or load balancer and are collected into the run
+/// summary while the rest of the run goes on.
+#[derive(Clone)]
+pub enum Error {
+    /// The config is unreadable or invalid.
+    Config(String),
+    /// A config, plan, snapshot or report file can't be read or written.
+    File { path: String, message: String },
+    /// Deleting the candidates would exceed the deletion limits.
+    Limits(Vec<String>),
+    /// The role of the account can't be assumed, or its credentials belong
+    /// to another account.
+    Account { account_id: String, message: String },
+    /// The load balancers of a region can't be listed.
+    Region { region: String, message: String },
+    /// A load balancer can't be described, backed up, tagged, deleted or
+    /// restored. `id` is the name of a classic ELB or the ARN of an ELBv2.
+    LoadBalancer { id: String, message: String },
+}
+
+impl Error {
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Error::Config(_) | Error::File { .. } | Error::Limits(_)
+        )
+    }
+
+    /// 2 for fatal errors, 1 for errors that only failed part of the run.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_fatal() {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(message) => f.debug_tuple("Config").field(message).finish(),
+            Error::File { path, message } => f
+                .debug_struct("File")
+                .field("path", path)
+                .field("message", message)
+                .finish(),
+            Error::Limits(violations) => f.debug_tuple("Limits").field(violations).finish(),
+            Error::Account {
+                account_id,
+                message,
+            } => f
+                .debug_struct("Account")
+                .field("account_id", account_id)
+                .field("message", message)
+                .finish(),
+            Error::Region { region, message } => f
+                .debug_struct("Region")
+                .field("region", region)
+                .field("message", message)
+                .finish(),
+            Error::LoadBalancer { id, message } => f
+                .debug_struct("LoadBalancer")
+                .field("id", id)
+                .field("message", message)
+                .finish(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(message) => write!(f, "invalid config: {}", message),
+            Error::File { path, message } => write!(f, "{}: {}", path, message),
+            Error::Limits(violations) => write!(
+                f,
+                "deletion limits exceeded, nothing was deleted: {}",
+                violations.join("; ")
+            ),
+            Error::Account {
+                account_id,
+                message,
+            } => write!(f, "account {}: {}", account_id, message),
+            Error::Region { region, message } => write!(f, "region {}: {}", region, message),
+            Error::LoadBalancer { id, message } => write!(f, "{}: {}", id, message),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use crate::models::{AppConfig, AwsAccount, LoadBalancerState, NamePattern, TagSelector};
+use crate::utils;
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+/// Returns the rule that keeps `value` from being deleted, or `None` when the
+/// `include_<list>`/`exclude_<list>` patterns allow it. Exclusions win over
+/// inclusions.
+pub fn pattern_exclusion_reason(
+    value: &str,
+    list: &str,
+    include: &[NamePattern],
+    exclude: &[NamePattern],
+) -> Option<String> {
+    if let Some(pattern) = exclude.iter().find(|p| p.matches(value)) {
+        return Some(format!("excluded by exclude_{} {}", list, pattern));
+    }
+    if !include.is_empty() && !include.iter().any(|p| p.matches(value)) {
+        return Some(format!("not matched by include_{}", list));
+    }
+    None
+}
+
+/// Returns why a load balancer with these tags has to be kept, or `None` when
+/// the tag selectors allow it to be deleted. Exclusions win over inclusions.
+pub fn tag_exclusion_reason(
+    tags: &HashMap<String, String>,
+    include_tags: &[TagSelector],
+    exclude_tags: &[TagSelector],
+) -> Option<String> {
+    if let Some(selector) = exclude_tags.iter().find(|s| s.matches(tags)) {
+        return Some(format!("excluded by tag selector {}", selector));
+    }
+    if let Some(selector) = include_tags.iter().find(|s| !s.matches(tags)) {
+        return Some(format!("not included by tag selector {}", selector));
+    }
+    None
+}
+
+/// Returns `Filtered` when the load balancer is outside the VPCs selected by
+/// `include_vpc_ids`/`exclude_vpc_ids` and the VPC tag selectors, or `None`
+/// when its VPC is in scope. `vpc_tags` are the tags of the load balancer's VPC.
+pub fn vpc_filter_state(
+    vpc_id: &str,
+    vpc_tags: Result<&HashMap<String, String>, &String>,
+    aws_account: &AwsAccount,
+) -> Option<LoadBalancerState> {
+    if aws_account.exclude_vpc_ids.iter().any(|id| id == vpc_id) {
+        return Some(LoadBalancerState::Filtered(format!(
+            "{} is in exclude_vpc_ids",
+            vpc_id
+        )));
+    }
+    if !aws_account.include_vpc_ids.is_empty()
+        && !aws_account.include_vpc_ids.iter().any(|id| id == vpc_id)
+    {
+        return Some(LoadBalancerState::Filtered(format!(
+            "{} is not in include_vpc_ids",
+            vpc_id
+        )));
+    }
+
+    if aws_account.include_vpc_tags.is_empty() && aws_account.exclude_vpc_tags.is_empty() {
+        return None;
+    }
+    match vpc_tags {
+        Ok(vpc_tags) => tag_exclusion_reason(
+            vpc_tags,
+            &aws_account.include_vpc_tags,
+            &aws_account.exclude_vpc_tags,
+        )
+        .map(|reason| LoadBalancerState::Filtered(format!("{} {}", vpc_id, reason))),
+        Err(e) => Some(LoadBalancerState::Errored(format!(
+            "error describing VPC tags: {}",
+            e
+        ))),
+    }
+}
+
+/// Decides the state of a load balancer that must not be judged by its
+/// metrics, or `None` when its metrics should be read. Classic ELBs have no
+/// `arn` to match.
+pub fn state_before_metrics(
+    lb_name: &str,
+    arn: Option<&str>,
+    created_time: &Option<DateTime<Utc>>,
+    tags: Result<&HashMap<String, String>, &String>,
+    conf: &AppConfig,
+    aws_account: &AwsAccount,
+) -> Option<LoadBalancerState> {
+    let name_reason = pattern_exclusion_reason(
+        lb_name,
+        "names",
+        &aws_account.include_names,
+        &aws_account.exclude_names,
+    );
+    let arn_reason = arn.and_then(|arn| {
+        pattern_exclusion_reason(
+            arn,
+            "arns",
+            &aws_account.include_arns,
+            &aws_account.exclude_arns,
+        )
+    });
+    if let Some(reason) = name_reason.or(arn_reason) {
+        return Some(LoadBalancerState::Skipped(reason));
+    }
+
+    let has_tag_selectors = !conf.include_tags.is_empty() || !conf.exclude_tags.is_empty();
+    let tag_state = match tags {
+        Ok(tags) => tag_exclusion_reason(tags, &conf.include_tags, &conf.exclude_tags)
+            .map(LoadBalancerState::Skipped),
+        Err(e) if has_tag_selectors => Some(LoadBalancerState::Errored(format!(
+            "error describing tags: {}",
+            e
+        ))),
+        Err(_) => None,
+    };
+    if tag_state.is_some() {
+        return tag_state;
+    }
+
+    if utils::is_too_new(created_time, conf.min_age_days) {
+        return Some(LoadBalancerState::Skipped("too new to judge".to_string()));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    fn selectors(selectors: &[&str]) -> Vec<TagSelector> {
+        selectors
+            .iter()
+            .map(|selector| TagSelector::from_str(selector).unwrap())
+            .collect()
+    }
+
+    fn tags(tags: &[(&str, &str)]) -> HashMap<String, String> {
+        tags.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn no_selectors_allow_everything() {
+        assert_eq!(tag_exclusion_reason(&tags(&[]), &[], &[]), None);
+    }
+
+    #[test]
+    fn includes_only_load_balancers_matching_every_include() {
+        let include = selectors(&["env!=prod", "team"]);
+        assert_eq!(
+            tag_exclusion_reason(&tags(&[("env", "dev"), ("team", "web")]), &include, &[]),
+            None
+        );
+        assert_eq!(
+            tag_exclusion_reason(&tags(&[("env", "prod"), ("team", "web")]), &include, &[]),
+            Some("not included by tag selector env!=prod".to_string())
+        );
+        assert_eq!(
+            tag_exclusion_reason(&tags(&[("env", "dev")]), &include, &[]),
+            Some("not included by tag selector team".to_string())
+        );
+    }
+
+    #[test]
+    fn excludes_load_balancers_matching_any_exclude() {
+        let exclude = selectors(&["janitor:keep=true", "!owner"]);
+        assert_eq!(
+            tag_exclusion_reason(&tags(&[("owner", "ops")]), &[], &exclude),
+            None
+        );
+        assert_eq!(
+            tag_exclusion_reason(
+                &tags(&[("owner", "ops"), ("janitor:keep", "true")]),
+                &[],
+                &exclude
+            ),
+            Some("excluded by tag selector janitor:keep=true".to_string())
+        );
+        assert_eq!(
+            tag_exclusion_reason(&tags(&[]), &[], &exclude),
+            Some("excluded by tag selector !owner".to_string())
+        );
+    }
+
+    #[test]
+    fn exclusions_win_over_inclusions() {
+        let lb_tags = tags(&[("env", "dev"), ("janitor:keep", "true")]);
+        assert_eq!(
+            tag_exclusion_reason(
+                &lb_tags,
+                &selectors(&["env=dev"]),
+                &selectors(&["janitor:keep=true"])
+            ),
+            Some("excluded by tag selector janitor:keep=true".to_string())
+        );
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<NamePattern> {
+        patterns
+            .iter()
+            .map(|pattern| NamePattern::from_str(pattern).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn no_patterns_allow_everything() {
+        assert_eq!(pattern_exclusion_reason("web", "names", &[], &[]), None);
+    }
+
+    #[test]
+    fn matches_globs_and_regexes() {
+        let exclude = patterns(&["k8s-*", "re:-dr-\\d+$"]);
+        assert_eq!(
+            pattern_exclusion_reason("web-1", "names", &[], &exclude),
+            None
+        );
+        assert_eq!(
+            pattern_exclusion_reason("k8s-ingress", "names", &[], &exclude),
+            Some("excluded by exclude_names k8s-*".to_string())
+        );
+        assert_eq!(
+            pattern_exclusion_reason("web-dr-2", "names", &[], &exclude),
+            Some("excluded by exclude_names re:-dr-\\d+$".to_string())
+        );
+    }
+
+    #[test]
+    fn includes_only_values_matching_an_include() {
+        let include = patterns(&["web-*", "api-*"]);
+        assert_eq!(
+            pattern_exclusion_reason("api-1", "arns", &include, &[]),
+            None
+        );
+        assert_eq!(
+            pattern_exclusion_reason("batch-1", "arns", &include, &[]),
+            Some("not matched by include_arns".to_string())
+        );
+    }
+
+    #[test]
+    fn pattern_exclusions_win_over_inclusions() {
+        assert_eq!(
+            pattern_exclusion_reason(
+                "web-dr",
+                "names",
+                &patterns(&["web-*"]),
+                &patterns(&["*-dr"])
+            ),
+            Some("excluded by exclude_names *-dr".to_string())
+        );
+    }
+
+    fn account(filters: &str) -> AwsAccount {
+        let account = format!(
+            "iam_role = \"arn:aws:iam::123456789012:role/lb-janitor\"\nregions = [\"eu-west-1\"]\n{}",
+            filters
+        );
+        toml::from_str(&account).unwrap()
+    }
+
+    #[test]
+    fn no_vpc_filters_keep_every_vpc() {
+        let error = "access denied".to_string();
+        assert_eq!(vpc_filter_state("vpc-1", Err(&error), &account("")), None);
+    }
+
+    #[test]
+    fn filters_by_vpc_id() {
+        let aws_account =
+            account("include_vpc_ids = [\"vpc-1\", \"vpc-2\"]\nexclude_vpc_ids = [\"vpc-2\"]");
+        let vpc_tags = tags(&[]);
+        assert_eq!(vpc_filter_state("vpc-1", Ok(&vpc_tags), &aws_account), None);
+        assert_eq!(
+            vpc_filter_state("vpc-2", Ok(&vpc_tags), &aws_account),
+            Some(LoadBalancerState::Filtered(
+                "vpc-2 is in exclude_vpc_ids".to_string()
+            ))
+        );
+        assert_eq!(
+            vpc_filter_state("vpc-3", Ok(&vpc_tags), &aws_account),
+            Some(LoadBalancerState::Filtered(
+                "vpc-3 is not in include_vpc_ids".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn filters_by_vpc_tags() {
+        let aws_account =
+            account("include_vpc_tags = [\"env=sandbox\"]\nexclude_vpc_tags = [\"shared\"]");
+        assert_eq!(
+            vpc_filter_state("vpc-1", Ok(&tags(&[("env", "sandbox")])), &aws_account),
+            None
+        );
+        assert_eq!(
+            vpc_filter_state("vpc-1", Ok(&tags(&[("env", "prod")])), &aws_account),
+            Some(LoadBalancerState::Filtered(
+                "vpc-1 not included by tag selector env=sandbox".to_string()
+            ))
+        );
+        assert_eq!(
+            vpc_filter_state(
+                "vpc-1",
+                Ok(&tags(&[("env", "sandbox"), ("shared", "yes")])),
+                &aws_account
+            ),
+            Some(LoadBalancerState::Filtered(
+                "vpc-1 excluded by tag selector shared".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn vpc_ids_are_checked_before_vpc_tags() {
+        let aws_account =
+            account("exclude_vpc_ids = [\"vpc-1\"]\ninclude_vpc_tags = [\"env=sandbox\"]");
+        let error = "access denied".to_string();
+        assert_eq!(
+            vpc_filter_state("vpc-1", Err(&error), &aws_account),
+            Some(LoadBalancerState::Filtered(
+                "vpc-1 is in exclude_vpc_ids".to_string()
+            ))
+        );
+        assert_eq!(
+            vpc_filter_state("vpc-2", Err(&error), &aws_account),
+            Some(LoadBalancerState::Errored(
+                "error describing VPC tags: access denied".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::account::AccountData;
+use crate::error::Error;
+use crate::models::LimitsConfig;
+
+use std::collections::BTreeMap;
+
+/// Checks the load balancers about to be deleted against the configured
+/// limits, before anything is deleted. `discovered` holds every account as
+/// it was discovered, for the share of each account's load balancers.
+/// Fails with every limit that would be exceeded.
+pub fn check_limits(
+    limits: &LimitsConfig,
+    deletions: &[AccountData],
+    discovered: &[AccountData],
+) -> Result<(), Error> {
+    let mut violations: Vec<String> = vec![];
+    let mut per_region: BTreeMap<(String, String), usize> = BTreeMap::new();
+    let mut run_total = 0;
+
+    for account in deletions {
+        let account_total = account.elbs.len() + account.elbv2s.len();
+        run_total += account_total;
+
+        let regions = account
+            .elbs
+            .iter()
+            .map(|elb| elb.region.to_string())
+            .chain(account.elbv2s.iter().map(|elbv2| elbv2.region.to_string()));
+        for region in regions {
+            *per_region
+                .entry((account.account_id.clone(), region))
+                .or_insert(0) += 1;
+        }
+
+        if let Some(max) = limits.max_per_account {
+            if account_total > max {
+                violations.push(format!(
+                    "{} deletions in account {} exceed max_per_account = {}",
+                    account_total, account.account_id, max
+                ));
+            }
+        }
+
+        if let Some(max_share) = limits.max_account_share {
+            let discovered_total = discovered
+                .iter()
+                .find(|discovered| discovered.account_id == account.account_id)
+                .map(|discovered| discovered.elbs.len() + discovered.elbv2s.len())
+                .unwrap_or(account_total);
+            if account_total > 0 && account_total as f64 > max_share * discovered_total as f64 {
+                violations.push(format!(
+                    "{} of {} load balancers in account {} exceed max_account_share = {}",
+                    account_total, discovered_total, account.account_id, max_share
+                ));
+            }
+        }
+    }
+
+    if let Some(max) = limits.max_per_region {
+        for ((account_id, region), count) in per_region.iter() {
+            if *count > max {
+                violations.push(format!(
+                    "{} deletions in account {} region {} exceed max_per_region = {}",
+                    count, account_id, region, max
+                ));
+            }
+        }
+    }
+
+    if let Some(max) = limits.max_per_run {
+        if run_total > max {
+            violations.push(format!(
+                "{} deletions exceed max_per_run = {}",
+                run_total, max
+            ));
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Limits(violations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elb::ElbData;
+    use crate::models::LoadBalancerState;
+
+    use aws_credential_types::provider::SharedCredentialsProvider;
+    use aws_credential_types::Credentials;
+    use aws_types::region::Region;
+    use std::collections::HashMap;
+
+    fn elb(name: &str, region: &'static str) -> ElbData {
+        ElbData {
+            name: name.to_string(),
+            state: LoadBalancerState::Inactive,
+            region: Region::from_static(region),
+            vpc_id: "vpc-0123456789abcdef0".to_string(),
+            created_time: None,
+            last_active_at: None,
+            idle_days: "30".to_string(),
+            tags: HashMap::new(),
+            tags_known: true,
+            evidence: vec![],
+            config_hash: String::new(),
+        }
+    }
+
+    fn account(account_id: &str, regions: &[&'static str]) -> AccountData {
+        AccountData {
+            account_id: account_id.to_string(),
+            credentials: SharedCredentialsProvider::new(Credentials::new(
+                "key", "secret", None, None, "test",
+            )),
+            elbs: regions
+                .iter()
+                .enumerate()
+                .map(|(i, region)| elb(&format!("lb-{}", i), region))
+                .collect(),
+            elbv2s: vec![],
+            errors: vec![],
+        }
+    }
+
+    fn violations(result: Result<(), Error>) -> Vec<String> {
+        match result {
+            Ok(()) => vec![],
+            Err(Error::Limits(violations)) => violations,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn no_limits_allow_everything() {
+        let deletions = vec![account("111111111111", &["eu-west-1"; 10])];
+        assert!(check_limits(&LimitsConfig::default(), &deletions, &deletions).is_ok());
+    }
+
+    #[test]
+    fn max_per_run_allows_equal_and_refuses_over() {
+        let limits = LimitsConfig {
+            max_per_run: Some(3),
+            ..LimitsConfig::default()
+        };
+        let equal = vec![
+            account("111111111111", &["eu-west-1", "eu-west-1"]),
+            account("222222222222", &["eu-west-1"]),
+        ];
+        assert!(check_limits(&limits, &equal, &equal).is_ok());
+
+        let over = vec![
+            account("111111111111", &["eu-west-1", "eu-west-1"]),
+            account("222222222222", &["eu-west-1", "us-east-1"]),
+        ];
+        assert_eq!(
+            violations(check_limits(&limits, &over, &over)),
+            vec!["4 deletions exceed max_per_run = 3"]
+        );
+    }
+
+    #[test]
+    fn max_per_account_allows_equal_and_refuses_over() {
+        let limits = LimitsConfig {
+            max_per_account: Some(2),
+            ..LimitsConfig::default()
+        };
+        let deletions = vec![
+            account("111111111111", &["eu-west-1", "eu-west-1"]),
+            account("222222222222", &["eu-west-1", "eu-west-1", "us-east-1"]),
+        ];
+        assert_eq!(
+            violations(check_limits(&limits, &deletions, &deletions)),
+            vec!["3 deletions in account 222222222222 exceed max_per_account = 2"]
+        );
+    }
+
+    #[test]
+    fn max_per_region_counts_each_account_region() {
+        let limits = LimitsConfig {
+            max_per_region: Some(2),
+            ..LimitsConfig::default()
+        };
+        let deletions = vec![
+            account("111111111111", &["eu-west-1", "eu-west-1", "us-east-1"]),
+            account("222222222222", &["eu-west-1", "eu-west-1", "eu-west-1"]),
+        ];
+        assert_eq!(
+            violations(check_limits(&limits, &deletions, &deletions)),
+            vec!["3 deletions in account 222222222222 region eu-west-1 exceed max_per_region = 2"]
+        );
+    }
+
+    #[test]
+    fn max_account_share_allows_equal_and_refuses_over() {
+        let limits = LimitsConfig {
+            max_account_share: Some(0.5),
+            ..LimitsConfig::default()
+        };
+        let discovered = vec![account("111111111111", &["eu-west-1"; 4])];
+
+        let equal = vec![account("111111111111", &["eu-west-1"; 2])];
+        assert!(check_limits(&limits, &equal, &discovered).is_ok());
+
+        let over = vec![account("111111111111", &["eu-west-1"; 3])];
+        assert_eq!(
+            violations(check_limits(&limits, &over, &discovered)),
+            vec!["3 of 4 load balancers in account 111111111111 exceed max_account_share = 0.5"]
+        );
+    }
+
+    #[test]
+    fn max_account_share_with_nothing_discovered() {
+        let limits = LimitsConfig {
+            max_account_share: Some(0.5),
+            ..LimitsConfig::default()
+        };
+        let empty = vec![account("111111111111", &[])];
+        assert!(check_limits(&limits, &empty, &empty).is_ok());
+
+        // An account missing from the discovery counts what is deleted as
+        // everything it has.
+        let deletions = vec![account("111111111111", &["eu-west-1"])];
+        assert_eq!(
+            violations(check_limits(&limits, &deletions, &[])),
+            vec!["1 of 1 load balancers in account 111111111111 exceed max_account_share = 0.5"]
+        );
+    }
+}
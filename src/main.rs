@@ -1,13 +1,31 @@
+mod account;
 mod cloudwatch;
+mod confirm;
+mod ec2;
 mod elb;
 mod elbv2;
+mod error;
+mod filters;
+mod limits;
+mod mark;
 mod models;
+mod plan;
+mod restore;
+mod retry;
+mod scoring;
+mod snapshot;
+mod sts;
+mod summary;
 mod utils;
+mod validate;
 
-use clap::Parser;
-use elb::process_account as process_elbs;
-use elbv2::process_account as process_elbv2s;
-use models::AppConfig;
+use account::AccountData;
+use clap::{Parser, Subcommand};
+use error::Error;
+use models::{AppConfig, RunOption};
+use std::process;
+use std::sync::Arc;
+use summary::RunSummary;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -15,65 +33,172 @@ struct Args {
     /// Config file
     #[arg(short = 'c', long = "config-file")]
     config_file: String,
+
+    /// Delete without asking for confirmation, even with `confirm = true`
+    #[arg(short = 'y', long = "yes")]
+    yes: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Delete the load balancers of a plan that are unchanged since it was made
+    Apply {
+        /// Plan file written by `run_option = "plan"`
+        #[arg(long = "plan")]
+        plan: String,
+    },
+    /// Recreate a deleted load balancer from its snapshot in the archive
+    Restore {
+        /// Snapshot file written before the load balancer was deleted
+        #[arg(long = "snapshot")]
+        snapshot: String,
+    },
+    /// Check the config file and report every problem with its location
+    Validate,
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
 
-    let conf = AppConfig::new(&args.config_file);
+    let result = match args.command {
+        Some(Command::Validate) => {
+            validate::validate(&args.config_file).map(|()| RunSummary::default())
+        }
+        command => match AppConfig::new(&args.config_file).map(Arc::new) {
+            Ok(conf) => {
+                retry::init(conf.retry.clone());
+                match command {
+                    Some(Command::Apply { plan }) => plan::apply(conf, &plan).await,
+                    Some(Command::Restore { snapshot }) => restore::restore(conf, &snapshot)
+                        .await
+                        .map(|()| RunSummary::default()),
+                    Some(Command::Validate) => unreachable!("validate doesn't load the config"),
+                    None => run(conf, args.yes).await,
+                }
+            }
+            Err(e) => Err(e),
+        },
+    };
 
-    // dbg!("conf {}", conf);
-    let mut elbv2_tasks = Vec::new();
-    let mut elb_tasks = Vec::new();
+    let exit_code = match result {
+        Ok(mut summary) => {
+            summary.retries = retry::counts();
+            summary.print();
+            summary.exit_code()
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit_code()
+        }
+    };
+    process::exit(exit_code);
+}
 
-    for aws_account in conf.aws.accounts {
-        let days = conf.days;
-        let run_option = conf.run_option.clone();
-        let regions = aws_account.regions.clone();
-        let vpc_ids = aws_account.vpc_ids.clone();
-        let iam_role = aws_account.iam_role.clone();
-        let account_id = utils::extract_account_id_from_role_arn(&iam_role)
-            .unwrap()
-            .clone();
+async fn run(conf: Arc<AppConfig>, yes: bool) -> Result<RunSummary, Error> {
+    if conf.run_option == RunOption::Unknown {
+        return Err(Error::Config("run option invalid".to_string()));
+    }
 
-        let elbv2_task = tokio::spawn(async move {
-            process_elbv2s(
-                account_id.as_str(),
-                run_option,
-                days,
-                iam_role.as_str(),
-                vpc_ids,
-                regions,
-            )
-            .await;
-        });
+    let mut summary = RunSummary::default();
+    let mut tasks = Vec::new();
 
-        let days = conf.days;
-        let run_option = conf.run_option.clone();
-        let iam_role = aws_account.iam_role.clone();
-        let regions = aws_account.regions.clone();
-        let vpc_ids = aws_account.vpc_ids.clone();
-        let account_id = utils::extract_account_id_from_role_arn(&iam_role)
-            .unwrap()
-            .clone();
+    for aws_account in conf.aws.accounts.iter() {
+        let task = tokio::spawn(account::discover_account(
+            Arc::clone(&conf),
+            aws_account.clone(),
+        ));
+        tasks.push((aws_account.iam_role.clone(), task));
+    }
 
-        let elb_task = tokio::spawn(async move {
-            process_elbs(
-                account_id.as_str(),
-                run_option,
-                days,
-                iam_role.as_str(),
-                vpc_ids,
-                regions,
-            )
-            .await;
-        });
+    let mut accounts: Vec<AccountData> = vec![];
+    for (iam_role, task) in tasks {
+        match task.await {
+            Ok(Ok(account)) => {
+                summary.add_errors(&account.account_id, account.errors.clone());
+                accounts.push(account);
+            }
+            Ok(Err(e)) if e.is_fatal() => return Err(e),
+            Ok(Err(e)) => {
+                println!("Skipping account: {}", e);
+                let account_id = match &e {
+                    Error::Account { account_id, .. } => account_id.clone(),
+                    _ => iam_role,
+                };
+                summary.add_errors(&account_id, vec![e]);
+            }
+            Err(e) => {
+                let e = Error::Account {
+                    account_id: iam_role.clone(),
+                    message: format!("error discovering account: {}", e),
+                };
+                println!("Skipping account: {}", e);
+                summary.add_errors(&iam_role, vec![e]);
+            }
+        }
+    }
 
-        elbv2_tasks.push(elbv2_task);
-        elb_tasks.push(elb_task);
+    match conf.run_option {
+        RunOption::List => (),
+        RunOption::Plan => {
+            let plan = plan::build_plan(&conf, &accounts);
+            let file_name = format!("outputs/{}_plan.json", conf.name);
+            plan::write_plan(&file_name, &plan).map_err(|message| Error::File {
+                path: file_name.clone(),
+                message,
+            })?;
+            println!(
+                "Wrote plan of {} load balancers to {}",
+                plan.load_balancers.len(),
+                file_name
+            );
+        }
+        RunOption::Mark => {
+            let mut due_accounts: Vec<AccountData> = vec![];
+            for account in accounts.iter() {
+                let due = mark::process_marks(Arc::clone(&conf), account).await;
+                summary.add_errors(&due.account_id, due.errors.clone());
+                due_accounts.push(due);
+            }
+            limits::check_limits(&conf.limits, &due_accounts, &accounts)?;
+            for account in due_accounts {
+                let account_id = account.account_id.clone();
+                let deletions = account::delete_load_balancers(Arc::clone(&conf), account).await;
+                summary.add_deletions(&account_id, deletions);
+            }
+        }
+        RunOption::Delete => {
+            let inactive_accounts = accounts
+                .iter()
+                .map(account::inactive_load_balancers)
+                .collect::<Vec<AccountData>>();
+            limits::check_limits(&conf.limits, &inactive_accounts, &accounts)?;
+            let inactive_accounts = if conf.confirm && !yes {
+                let mut confirmed_accounts: Vec<AccountData> = vec![];
+                for account in inactive_accounts.iter() {
+                    match confirm::confirm_account(account) {
+                        Some(confirmed) => confirmed_accounts.push(confirmed),
+                        None => {
+                            println!("Aborted, nothing was deleted");
+                            return Ok(summary);
+                        }
+                    }
+                }
+                confirmed_accounts
+            } else {
+                inactive_accounts
+            };
+            for account in inactive_accounts {
+                let account_id = account.account_id.clone();
+                let deletions = account::delete_load_balancers(Arc::clone(&conf), account).await;
+                summary.add_deletions(&account_id, deletions);
+            }
+        }
+        RunOption::Unknown => (),
     }
 
-    futures::future::join_all(elbv2_tasks).await;
-    futures::future::join_all(elb_tasks).await;
+    Ok(summary)
 }
@@ -0,0 +1,354 @@
+use crate::account::AccountData;
+use crate::elb::{self, ElbData};
+use crate::elbv2::{self, ElbV2Data};
+use crate::error::Error;
+use crate::models::{AppConfig, LoadBalancerState};
+use crate::retry;
+use crate::sts;
+
+use aws_credential_types::provider::SharedCredentialsProvider;
+use aws_sdk_elasticloadbalancing::Client as ELBClient;
+use aws_sdk_elasticloadbalancingv2::Client as ELBv2Client;
+use aws_types::region::Region;
+use chrono::{Duration, NaiveDate, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const IDLE_SINCE_TAG: &str = "janitor:idle-since";
+pub const SCHEDULED_DELETION_TAG: &str = "janitor:scheduled-deletion";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkAction {
+    /// Idle and not marked yet.
+    Mark,
+    /// Marked, but active again, skipped or filtered.
+    Unmark,
+    /// Marked, still idle and past its scheduled deletion date.
+    Delete,
+}
+
+/// Decides what `mark` mode does with a load balancer, or `None` when it is
+/// left alone: not marked and not idle, marked but not due yet, or its
+/// activity or its tags (`None`) could not be determined. Without its tags a
+/// marked load balancer would look unmarked and have its countdown restarted.
+///
+/// A marked load balancer is due on its `janitor:scheduled-deletion` date, so
+/// that owners can postpone it by editing the tag, or `mark_days` after its
+/// `janitor:idle-since` date when the scheduled date can't be read. Marks are
+/// removed once it is no longer idle, and also once it is skipped or filtered,
+/// so that lifting an exclusion starts a new countdown instead of deleting it
+/// right away.
+pub fn mark_action(
+    state: &LoadBalancerState,
+    tags: Option<&HashMap<String, String>>,
+    mark_days: i64,
+    today: NaiveDate,
+) -> Option<MarkAction> {
+    let tags = tags?;
+    let marked = tags.contains_key(IDLE_SINCE_TAG) || tags.contains_key(SCHEDULED_DELETION_TAG);
+    let date = |key| {
+        tags.get(key)
+            .and_then(|date: &String| NaiveDate::parse_from_str(date, DATE_FORMAT).ok())
+    };
+
+    match state {
+        LoadBalancerState::Inactive => {
+            let due = date(SCHEDULED_DELETION_TAG).or_else(|| {
+                date(IDLE_SINCE_TAG).map(|idle_since| idle_since + Duration::days(mark_days))
+            });
+            match due {
+                None => Some(MarkAction::Mark),
+                Some(due) if due <= today => Some(MarkAction::Delete),
+                Some(_) => None,
+            }
+        }
+        LoadBalancerState::Active
+        | LoadBalancerState::Skipped(_)
+        | LoadBalancerState::Filtered(_)
+            if marked =>
+        {
+            Some(MarkAction::Unmark)
+        }
+        _ => None,
+    }
+}
+
+fn mark_tags(mark_days: i64, today: NaiveDate) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+    tags.insert(
+        IDLE_SINCE_TAG.to_string(),
+        today.format(DATE_FORMAT).to_string(),
+    );
+    tags.insert(
+        SCHEDULED_DELETION_TAG.to_string(),
+        (today + Duration::days(mark_days))
+            .format(DATE_FORMAT)
+            .to_string(),
+    );
+    tags
+}
+
+/// Marks the newly idle load balancers of an account and unmarks the ones
+/// that became active again, skipped or filtered. Returns the ones that
+/// stayed idle past their scheduled deletion date, which are due for
+/// deletion, with the marks that could not be updated as errors.
+pub async fn process_marks(conf: Arc<AppConfig>, account: &AccountData) -> AccountData {
+    let mut due = AccountData {
+        account_id: account.account_id.clone(),
+        credentials: account.credentials.clone(),
+        elbs: vec![],
+        elbv2s: vec![],
+        errors: vec![],
+    };
+    if let Err(e) = sts::verify_account(&account.credentials, &account.account_id).await {
+        println!("Skipping marks in account {}: {}", account.account_id, e);
+        due.errors.push(Error::Account {
+            account_id: account.account_id.clone(),
+            message: format!("marks not updated: {}", e),
+        });
+        return due;
+    }
+
+    let today = Utc::now().date_naive();
+    let tags = mark_tags(conf.mark_days, today);
+    let tag_keys = [IDLE_SINCE_TAG, SCHEDULED_DELETION_TAG];
+    let (mut marked, mut unmarked) = (0, 0);
+
+    for elb in account.elbs.iter() {
+        let lb_tags = elb.tags_known.then_some(&elb.tags);
+        let action = mark_action(&elb.state, lb_tags, conf.mark_days, today);
+        let client = match action {
+            Some(MarkAction::Mark) | Some(MarkAction::Unmark) => {
+                ELBClient::new(&client_config(&account.credentials, &elb.region).await)
+            }
+            Some(MarkAction::Delete) => {
+                due.elbs.push(elb.clone());
+                continue;
+            }
+            None => continue,
+        };
+        let result = match action {
+            Some(MarkAction::Mark) => elb::add_elb_tags(&client, &elb.name, &tags).await,
+            _ => elb::remove_elb_tags(&client, &elb.name, &tag_keys).await,
+        };
+        match (result, action) {
+            (Ok(()), Some(MarkAction::Mark)) => {
+                println!("Marked ELB {} as idle", elb.name);
+                marked += 1;
+            }
+            (Ok(()), _) => {
+                println!("Unmarked ELB {}, it is {}", elb.name, elb.state);
+                unmarked += 1;
+            }
+            (Err(e), _) => {
+                println!("Error updating marks of ELB {}: {}", elb.name, e);
+                due.errors.push(Error::LoadBalancer {
+                    id: elb.name.clone(),
+                    message: e,
+                });
+            }
+        }
+    }
+
+    for elbv2 in account.elbv2s.iter() {
+        let lb_tags = elbv2.tags_known.then_some(&elbv2.tags);
+        let action = mark_action(&elbv2.state, lb_tags, conf.mark_days, today);
+        let client = match action {
+            Some(MarkAction::Mark) | Some(MarkAction::Unmark) => {
+                ELBv2Client::new(&client_config(&account.credentials, &elbv2.region).await)
+            }
+            Some(MarkAction::Delete) => {
+                due.elbv2s.push(elbv2.clone());
+                continue;
+            }
+            None => continue,
+        };
+        let result = match action {
+            Some(MarkAction::Mark) => elbv2::add_elbv2_tags(&client, &elbv2.arn, &tags).await,
+            _ => elbv2::remove_elbv2_tags(&client, &elbv2.arn, &tag_keys).await,
+        };
+        match (result, action) {
+            (Ok(()), Some(MarkAction::Mark)) => {
+                println!("Marked ELBv2 {} as idle", elbv2.arn);
+                marked += 1;
+            }
+            (Ok(()), _) => {
+                println!("Unmarked ELBv2 {}, it is {}", elbv2.arn, elbv2.state);
+                unmarked += 1;
+            }
+            (Err(e), _) => {
+                println!("Error updating marks of ELBv2 {}: {}", elbv2.arn, e);
+                due.errors.push(Error::LoadBalancer {
+                    id: elbv2.arn.clone(),
+                    message: e,
+                });
+            }
+        }
+    }
+
+    println!(
+        "Account {}: {} marked, {} unmarked, {} due for deletion",
+        due.account_id,
+        marked,
+        unmarked,
+        due.elbs.len() + due.elbv2s.len()
+    );
+    log_due(&due.elbs, &due.elbv2s);
+
+    due
+}
+
+fn log_due(elbs: &[ElbData], elbv2s: &[ElbV2Data]) {
+    for elb in elbs {
+        println!(
+            "ELB {} marked idle since {}",
+            elb.name,
+            elb.tags.get(IDLE_SINCE_TAG).cloned().unwrap_or_default()
+        );
+    }
+    for elbv2 in elbv2s {
+        println!(
+            "ELBv2 {} marked idle since {}",
+            elbv2.arn,
+            elbv2.tags.get(IDLE_SINCE_TAG).cloned().unwrap_or_default()
+        );
+    }
+}
+
+async fn client_config(
+    credentials: &SharedCredentialsProvider,
+    region: &Region,
+) -> aws_config::SdkConfig {
+    aws_config::from_env()
+        .retry_config(retry::sdk_retry_config())
+        .credentials_provider(credentials.clone())
+        .region(region.clone())
+        .load()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, DATE_FORMAT).unwrap()
+    }
+
+    fn marks(idle_since: &str, scheduled_deletion: &str) -> HashMap<String, String> {
+        let mut tags = HashMap::new();
+        tags.insert(IDLE_SINCE_TAG.to_string(), idle_since.to_string());
+        tags.insert(
+            SCHEDULED_DELETION_TAG.to_string(),
+            scheduled_deletion.to_string(),
+        );
+        tags
+    }
+
+    #[test]
+    fn marks_unmarked_idle_load_balancers() {
+        let action = mark_action(
+            &LoadBalancerState::Inactive,
+            Some(&HashMap::new()),
+            14,
+            date("2026-01-01"),
+        );
+        assert_eq!(action, Some(MarkAction::Mark));
+    }
+
+    #[test]
+    fn deletes_once_due() {
+        let tags = marks("2026-01-01", "2026-01-15");
+        let action =
+            |today| mark_action(&LoadBalancerState::Inactive, Some(&tags), 14, date(today));
+        assert_eq!(action("2026-01-14"), None);
+        assert_eq!(action("2026-01-15"), Some(MarkAction::Delete));
+        assert_eq!(action("2026-02-01"), Some(MarkAction::Delete));
+    }
+
+    #[test]
+    fn follows_the_scheduled_deletion_date() {
+        // Postponed by hand, and mark_days lowered since the mark.
+        let tags = marks("2026-01-01", "2026-03-01");
+        let action = |today| mark_action(&LoadBalancerState::Inactive, Some(&tags), 3, date(today));
+        assert_eq!(action("2026-01-15"), None);
+        assert_eq!(action("2026-02-28"), None);
+        assert_eq!(action("2026-03-01"), Some(MarkAction::Delete));
+    }
+
+    #[test]
+    fn falls_back_to_idle_since_without_a_scheduled_date() {
+        let tags = marks("2026-01-01", "soon");
+        let action =
+            |today| mark_action(&LoadBalancerState::Inactive, Some(&tags), 14, date(today));
+        assert_eq!(action("2026-01-14"), None);
+        assert_eq!(action("2026-01-15"), Some(MarkAction::Delete));
+
+        let mut tags = marks("2026-01-01", "");
+        tags.remove(SCHEDULED_DELETION_TAG);
+        let action = mark_action(
+            &LoadBalancerState::Inactive,
+            Some(&tags),
+            14,
+            date("2026-01-15"),
+        );
+        assert_eq!(action, Some(MarkAction::Delete));
+    }
+
+    #[test]
+    fn remarks_unparsable_marks() {
+        let tags = marks("last tuesday", "soon");
+        let action = mark_action(
+            &LoadBalancerState::Inactive,
+            Some(&tags),
+            14,
+            date("2026-01-15"),
+        );
+        assert_eq!(action, Some(MarkAction::Mark));
+    }
+
+    #[test]
+    fn unmarks_load_balancers_that_are_no_longer_idle() {
+        let tags = marks("2026-01-01", "2026-01-15");
+        let today = date("2026-01-05");
+        let states = [
+            LoadBalancerState::Active,
+            LoadBalancerState::Skipped("excluded by tag selector janitor:keep=true".to_string()),
+            LoadBalancerState::Filtered("vpc-1 is in exclude_vpc_ids".to_string()),
+        ];
+        for state in states {
+            assert_eq!(
+                mark_action(&state, Some(&tags), 14, today),
+                Some(MarkAction::Unmark),
+                "{}",
+                state
+            );
+            assert_eq!(
+                mark_action(&state, Some(&HashMap::new()), 14, today),
+                None,
+                "{}",
+                state
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_undetermined_load_balancers_alone() {
+        let tags = marks("2026-01-01", "2026-01-15");
+        let today = date("2026-02-01");
+        let unknown = LoadBalancerState::Unknown("throttled".to_string());
+        let errored = LoadBalancerState::Errored("error describing tags".to_string());
+        assert_eq!(mark_action(&unknown, Some(&tags), 14, today), None);
+        assert_eq!(mark_action(&errored, Some(&tags), 14, today), None);
+        assert_eq!(
+            mark_action(&LoadBalancerState::Inactive, None, 14, today),
+            None
+        );
+        assert_eq!(
+            mark_action(&LoadBalancerState::Active, None, 14, today),
+            None
+        );
+    }
+}
@@ -1,5 +1,8 @@
+use crate::error::Error;
+use crate::validate::{check_config, Problem};
 use serde::{Deserialize, Serialize};
 use serde::{Deserializer, Serializer};
+use std::collections::HashMap;
 use std::fmt;
 use std::fs;
 use std::str::FromStr;
@@ -7,6 +10,8 @@ use std::str::FromStr;
 #[derive(Clone, PartialEq)]
 pub enum RunOption {
     List,
+    Plan,
+    Mark,
     Delete,
     Unknown,
 }
@@ -18,6 +23,8 @@ impl Serialize for RunOption {
     {
         serializer.serialize_str(match *self {
             RunOption::List => "list",
+            RunOption::Plan => "plan",
+            RunOption::Mark => "mark",
             RunOption::Delete => "delete",
             _ => "unknown",
         })
@@ -30,10 +37,11 @@ impl<'de> Deserialize<'de> for RunOption {
         T: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        Ok(match s.as_str() {
-            "list" => RunOption::List,
-            "delete" => RunOption::Delete,
-            _ => RunOption::Unknown,
+        RunOption::from_str(&s).map_err(|()| {
+            serde::de::Error::custom(format!(
+                "unknown run_option {:?}, expected \"list\", \"plan\", \"mark\" or \"delete\"",
+                s
+            ))
         })
     }
 }
@@ -42,6 +50,8 @@ impl fmt::Debug for RunOption {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             RunOption::List => write!(f, "List"),
+            RunOption::Plan => write!(f, "Plan"),
+            RunOption::Mark => write!(f, "Mark"),
             RunOption::Delete => write!(f, "Delete"),
             _ => write!(f, "Unknown"),
         }
@@ -52,6 +62,8 @@ impl fmt::Display for RunOption {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
             RunOption::List => write!(f, "List"),
+            RunOption::Plan => write!(f, "Plan"),
+            RunOption::Mark => write!(f, "Mark"),
             RunOption::Delete => write!(f, "Delete"),
             _ => write!(f, "Unknown"),
         }
@@ -64,12 +76,15 @@ impl FromStr for RunOption {
     fn from_str(input: &str) -> Result<RunOption, Self::Err> {
         match input.to_lowercase().as_str() {
             "list" => Ok(RunOption::List),
+            "plan" => Ok(RunOption::Plan),
+            "mark" => Ok(RunOption::Mark),
             "delete" => Ok(RunOption::Delete),
             _ => Err(()),
         }
     }
 }
 
+#[allow(dead_code)]
 #[derive(Clone, PartialEq)]
 pub enum ListFormat {
     Tabled,
@@ -110,46 +125,579 @@ impl FromStr for ListFormat {
 pub enum LoadBalancerState {
     Active,
     Inactive,
+    /// Activity could not be determined, e.g. CloudWatch was throttled or
+    /// returned partial data.
+    Unknown(String),
+    /// The load balancer itself could not be inspected.
+    Errored(String),
+    /// The load balancer was deliberately not judged, e.g. it is too new.
+    Skipped(String),
+    /// The load balancer is outside the VPCs selected for this account.
+    Filtered(String),
+}
+
+impl LoadBalancerState {
+    pub fn is_skipped(&self) -> bool {
+        matches!(*self, LoadBalancerState::Skipped(_))
+    }
+
+    pub fn is_filtered(&self) -> bool {
+        matches!(*self, LoadBalancerState::Filtered(_))
+    }
+
+    pub fn is_undetermined(&self) -> bool {
+        matches!(
+            *self,
+            LoadBalancerState::Unknown(_) | LoadBalancerState::Errored(_)
+        )
+    }
 }
 
 impl fmt::Debug for LoadBalancerState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             LoadBalancerState::Active => write!(f, "Active"),
             LoadBalancerState::Inactive => write!(f, "Inactive"),
+            LoadBalancerState::Unknown(reason) => write!(f, "Unknown({:?})", reason),
+            LoadBalancerState::Errored(reason) => write!(f, "Errored({:?})", reason),
+            LoadBalancerState::Skipped(reason) => write!(f, "Skipped({:?})", reason),
+            LoadBalancerState::Filtered(reason) => write!(f, "Filtered({:?})", reason),
         }
     }
 }
 
 impl fmt::Display for LoadBalancerState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             LoadBalancerState::Active => write!(f, "Active"),
             LoadBalancerState::Inactive => write!(f, "Inactive"),
+            LoadBalancerState::Unknown(reason) => write!(f, "Unknown ({})", reason),
+            LoadBalancerState::Errored(reason) => write!(f, "Errored ({})", reason),
+            LoadBalancerState::Skipped(reason) => write!(f, "Skipped ({})", reason),
+            LoadBalancerState::Filtered(reason) => write!(f, "Filtered ({})", reason),
+        }
+    }
+}
+
+/// A tag condition written as `key=value`, `key!=value`, `key` (tag is set)
+/// or `!key` (tag is not set).
+#[derive(Clone, PartialEq)]
+pub enum TagSelector {
+    Equals(String, String),
+    NotEquals(String, String),
+    Exists(String),
+    Absent(String),
+}
+
+impl TagSelector {
+    pub fn matches(&self, tags: &HashMap<String, String>) -> bool {
+        match self {
+            TagSelector::Equals(key, value) => tags.get(key) == Some(value),
+            TagSelector::NotEquals(key, value) => tags.get(key) != Some(value),
+            TagSelector::Exists(key) => tags.contains_key(key),
+            TagSelector::Absent(key) => !tags.contains_key(key),
+        }
+    }
+}
+
+impl Serialize for TagSelector {
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+    where
+        T: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TagSelector {
+    fn deserialize<T>(deserializer: T) -> Result<Self, T::Error>
+    where
+        T: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        TagSelector::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Debug for TagSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TagSelector({})", self)
+    }
+}
+
+impl fmt::Display for TagSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagSelector::Equals(key, value) => write!(f, "{}={}", key, value),
+            TagSelector::NotEquals(key, value) => write!(f, "{}!={}", key, value),
+            TagSelector::Exists(key) => write!(f, "{}", key),
+            TagSelector::Absent(key) => write!(f, "!{}", key),
+        }
+    }
+}
+
+impl FromStr for TagSelector {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<TagSelector, Self::Err> {
+        let input = input.trim();
+        // `!env=prod` would select a tag key literally named `!env`, which
+        // never matches, so an exclusion written that way would do nothing.
+        if let Some((key, value)) = input.strip_prefix('!').and_then(|s| s.split_once('=')) {
+            let key = key.trim_end_matches('!');
+            if !key.is_empty() {
+                return Err(format!(
+                    "invalid tag selector {:?}: `!` can't be combined with a value, write \"{}!={}\" to select a tag with another value",
+                    input, key, value
+                ));
+            }
+        }
+        let selector = if let Some((key, value)) = input.split_once("!=") {
+            TagSelector::NotEquals(key.to_string(), value.to_string())
+        } else if let Some((key, value)) = input.split_once('=') {
+            TagSelector::Equals(key.to_string(), value.to_string())
+        } else if let Some(key) = input.strip_prefix('!') {
+            TagSelector::Absent(key.to_string())
+        } else {
+            TagSelector::Exists(input.to_string())
+        };
+
+        match selector {
+            TagSelector::Equals(ref key, _)
+            | TagSelector::NotEquals(ref key, _)
+            | TagSelector::Exists(ref key)
+            | TagSelector::Absent(ref key)
+                if key.is_empty() =>
+            {
+                Err(format!("invalid tag selector {:?}: missing tag key", input))
+            }
+            _ => Ok(selector),
+        }
+    }
+}
+
+/// A glob such as `k8s-*`, or a regular expression when prefixed with `re:`.
+#[derive(Clone)]
+pub enum NamePattern {
+    Glob(glob::Pattern),
+    Regex(regex::Regex),
+}
+
+impl NamePattern {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            NamePattern::Glob(pattern) => pattern.matches(value),
+            NamePattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl Serialize for NamePattern {
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+    where
+        T: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for NamePattern {
+    fn deserialize<T>(deserializer: T) -> Result<Self, T::Error>
+    where
+        T: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        NamePattern::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Debug for NamePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NamePattern({})", self)
+    }
+}
+
+impl fmt::Display for NamePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NamePattern::Glob(pattern) => write!(f, "{}", pattern.as_str()),
+            NamePattern::Regex(regex) => write!(f, "re:{}", regex.as_str()),
+        }
+    }
+}
+
+impl FromStr for NamePattern {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<NamePattern, Self::Err> {
+        match input.strip_prefix("re:") {
+            Some(regex) => regex::Regex::new(regex)
+                .map(NamePattern::Regex)
+                .map_err(|e| format!("invalid regex {:?}: {}", regex, e)),
+            None => glob::Pattern::new(input)
+                .map(NamePattern::Glob)
+                .map_err(|e| format!("invalid glob {:?}: {}", input, e)),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricDimension {
+    LoadBalancer,
+    TargetGroup,
+}
+
+impl fmt::Debug for MetricDimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MetricDimension::LoadBalancer => write!(f, "LoadBalancer"),
+            MetricDimension::TargetGroup => write!(f, "TargetGroup"),
+        }
+    }
+}
+
+impl fmt::Display for MetricDimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MetricDimension::LoadBalancer => write!(f, "LoadBalancer"),
+            MetricDimension::TargetGroup => write!(f, "TargetGroup"),
         }
     }
 }
 
+fn default_metric_dimension() -> MetricDimension {
+    MetricDimension::LoadBalancer
+}
+
+fn default_metric_stat() -> String {
+    "Sum".to_string()
+}
+
+fn default_metric_weight() -> f64 {
+    1.0
+}
+
+/// A single CloudWatch metric that contributes to a load balancer's activity
+/// score. The metric adds `weight` to the score when the sum of its datapoints
+/// over the lookback window is greater than `threshold`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricRule {
+    pub metric_name: String,
+    #[serde(default = "default_metric_stat")]
+    pub stat: String,
+    #[serde(default)]
+    pub threshold: f64,
+    #[serde(default = "default_metric_weight")]
+    pub weight: f64,
+    #[serde(default = "default_metric_dimension")]
+    pub dimension: MetricDimension,
+    /// Target types (`instance`, `ip`, `lambda`, `alb`) a `target_group` rule
+    /// applies to. Empty means every target group.
+    #[serde(default)]
+    pub target_types: Vec<String>,
+}
+
+impl MetricRule {
+    pub fn new(metric_name: &str, stat: &str, weight: f64, dimension: MetricDimension) -> Self {
+        MetricRule {
+            metric_name: metric_name.to_string(),
+            stat: stat.to_string(),
+            threshold: 0.0,
+            weight,
+            dimension,
+            target_types: vec![],
+        }
+    }
+
+    pub fn with_target_types(mut self, target_types: &[&str]) -> Self {
+        self.target_types = target_types.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    pub fn applies_to_target_type(&self, target_type: &str) -> bool {
+        self.target_types.is_empty() || self.target_types.iter().any(|t| t == target_type)
+    }
+}
+
+impl fmt::Debug for MetricRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricRule")
+            .field("metric_name", &self.metric_name)
+            .field("stat", &self.stat)
+            .field("threshold", &self.threshold)
+            .field("weight", &self.weight)
+            .field("dimension", &self.dimension)
+            .field("target_types", &self.target_types)
+            .finish()
+    }
+}
+
+/// Per load balancer type metric rules. A load balancer is considered active
+/// once the combined weight of its matching rules reaches `active_score`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringConfig {
+    pub active_score: f64,
+    pub classic: Vec<MetricRule>,
+    pub application: Vec<MetricRule>,
+    pub network: Vec<MetricRule>,
+    pub gateway: Vec<MetricRule>,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        ScoringConfig {
+            active_score: 1.0,
+            classic: vec![
+                MetricRule::new("RequestCount", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new(
+                    "EstimatedProcessedBytes",
+                    "Sum",
+                    1.0,
+                    MetricDimension::LoadBalancer,
+                ),
+                MetricRule::new(
+                    "HealthyHostCount",
+                    "Minimum",
+                    0.5,
+                    MetricDimension::LoadBalancer,
+                ),
+            ],
+            application: vec![
+                MetricRule::new("RequestCount", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new("ProcessedBytes", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new(
+                    "ActiveConnectionCount",
+                    "Sum",
+                    1.0,
+                    MetricDimension::LoadBalancer,
+                ),
+                MetricRule::new(
+                    "HTTPCode_ELB_3XX_Count",
+                    "Sum",
+                    1.0,
+                    MetricDimension::LoadBalancer,
+                ),
+                MetricRule::new(
+                    "HTTPCode_ELB_4XX_Count",
+                    "Sum",
+                    1.0,
+                    MetricDimension::LoadBalancer,
+                ),
+                MetricRule::new(
+                    "HealthyHostCount",
+                    "Minimum",
+                    0.5,
+                    MetricDimension::TargetGroup,
+                )
+                .with_target_types(&["instance", "ip"]),
+                // Lambda target groups never report HealthyHostCount.
+                MetricRule::new("RequestCount", "Sum", 1.0, MetricDimension::TargetGroup)
+                    .with_target_types(&["lambda"]),
+                MetricRule::new("LambdaUserError", "Sum", 1.0, MetricDimension::TargetGroup)
+                    .with_target_types(&["lambda"]),
+                MetricRule::new(
+                    "LambdaInternalError",
+                    "Sum",
+                    1.0,
+                    MetricDimension::TargetGroup,
+                )
+                .with_target_types(&["lambda"]),
+            ],
+            network: vec![
+                MetricRule::new("ActiveFlowCount", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new("NewFlowCount", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new("ProcessedBytes", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new(
+                    "HealthyHostCount",
+                    "Minimum",
+                    0.5,
+                    MetricDimension::TargetGroup,
+                ),
+            ],
+            gateway: vec![
+                MetricRule::new("ActiveFlowCount", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new("NewFlowCount", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new("ProcessedBytes", "Sum", 1.0, MetricDimension::LoadBalancer),
+                MetricRule::new(
+                    "HealthyHostCount",
+                    "Minimum",
+                    0.5,
+                    MetricDimension::TargetGroup,
+                ),
+            ],
+        }
+    }
+}
+
+impl fmt::Debug for ScoringConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScoringConfig")
+            .field("active_score", &self.active_score)
+            .field("classic", &self.classic)
+            .field("application", &self.application)
+            .field("network", &self.network)
+            .field("gateway", &self.gateway)
+            .finish()
+    }
+}
+
+/// Upper bounds on how many load balancers a single run may delete. A run
+/// that would exceed any of them deletes nothing.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_per_run: Option<usize>,
+    pub max_per_account: Option<usize>,
+    pub max_per_region: Option<usize>,
+    /// Largest share, between 0 and 1, of the load balancers discovered in
+    /// an account that may be deleted.
+    pub max_account_share: Option<f64>,
+}
+
+impl fmt::Debug for LimitsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LimitsConfig")
+            .field("max_per_run", &self.max_per_run)
+            .field("max_per_account", &self.max_per_account)
+            .field("max_per_region", &self.max_per_region)
+            .field("max_account_share", &self.max_account_share)
+            .finish()
+    }
+}
+
+/// How AWS calls are retried when they are throttled or fail transiently.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Attempts per call, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every further retry.
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Share of every delay that is randomized, between 0 and 1.
+    pub jitter: f64,
+    /// Error codes that are retried.
+    pub retry_errors: Vec<String>,
+    /// Whether calls that timed out or got no response are retried.
+    pub retry_timeouts: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay_ms: 200,
+            max_delay_ms: 20_000,
+            jitter: 1.0,
+            retry_errors: [
+                "Throttling",
+                "ThrottlingException",
+                "ThrottledException",
+                "RequestThrottledException",
+                "TooManyRequestsException",
+                "RequestLimitExceeded",
+                "RequestThrottled",
+                "InternalFailure",
+                "InternalError",
+                "ServiceUnavailable",
+                "RequestTimeout",
+                "RequestTimeoutException",
+            ]
+            .iter()
+            .map(|code| code.to_string())
+            .collect(),
+            retry_timeouts: true,
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay_ms", &self.base_delay_ms)
+            .field("max_delay_ms", &self.max_delay_ms)
+            .field("jitter", &self.jitter)
+            .field("retry_errors", &self.retry_errors)
+            .field("retry_timeouts", &self.retry_timeouts)
+            .finish()
+    }
+}
+
 #[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AppConfig {
     pub name: String,
     pub run_option: RunOption,
     pub days: i64,
+    /// Load balancers created less than this many days ago are not judged.
+    #[serde(default)]
+    pub min_age_days: i64,
+    /// Turn off deletion protection of ELBv2s before deleting them instead of
+    /// skipping them.
+    #[serde(default)]
+    pub disable_deletion_protection: bool,
+    /// Directory where a JSON snapshot of every load balancer is written
+    /// before it is deleted.
+    #[serde(default = "default_archive_dir")]
+    pub archive_dir: String,
+    /// In `delete` mode, show the load balancers of each account and ask
+    /// before deleting them, unless `--yes` is passed.
+    #[serde(default)]
+    pub confirm: bool,
+    /// In `mark` mode, load balancers are deleted once they have been marked
+    /// idle for this many days.
+    #[serde(default = "default_mark_days")]
+    pub mark_days: i64,
+    /// Only load balancers matching every one of these tag selectors are
+    /// considered for deletion.
+    #[serde(default)]
+    pub include_tags: Vec<TagSelector>,
+    /// Load balancers matching any of these tag selectors are never deleted.
+    #[serde(default)]
+    pub exclude_tags: Vec<TagSelector>,
+    #[serde(default)]
+    pub scoring: ScoringConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub retry: RetryPolicy,
     pub aws: AwsConfig,
 }
 
+fn default_archive_dir() -> String {
+    "archive".to_string()
+}
+
+fn default_mark_days() -> i64 {
+    7
+}
+
 impl AppConfig {
-    pub fn new(filepath: &str) -> Self {
-        let conf = fs::read_to_string(filepath);
-        match conf {
-            Ok(conf) => match toml::from_str(&conf) {
-                Ok(conf) => conf,
-                Err(e) => panic!("Error parsing config as toml! {}", e),
-            },
-            Err(e) => {
-                panic!("Error reading config file! {}", e);
-            }
+    pub fn new(filepath: &str) -> Result<Self, Error> {
+        let conf = fs::read_to_string(filepath).map_err(|e| Error::File {
+            path: filepath.to_string(),
+            message: format!("error reading config file: {}", e),
+        })?;
+        let (warnings, errors): (Vec<Problem>, Vec<Problem>) = check_config(&conf)
+            .into_iter()
+            .partition(|problem| problem.warning);
+        for warning in warnings.iter() {
+            println!("{}:{}", filepath, warning);
+        }
+        if !errors.is_empty() {
+            let errors: Vec<String> = errors
+                .iter()
+                .map(|problem| format!("{}:{}", filepath, problem))
+                .collect();
+            return Err(Error::Config(errors.join("\n  ")));
         }
+        let conf = toml::from_str::<AppConfig>(&conf)
+            .map_err(|e| Error::Config(format!("error parsing {} as toml: {}", filepath, e)))?;
+        Ok(conf)
     }
 }
 
@@ -159,6 +707,19 @@ impl fmt::Display for AppConfig {
             .field("name", &self.name)
             .field("run_option", &self.run_option)
             .field("days", &self.days)
+            .field("min_age_days", &self.min_age_days)
+            .field(
+                "disable_deletion_protection",
+                &self.disable_deletion_protection,
+            )
+            .field("archive_dir", &self.archive_dir)
+            .field("confirm", &self.confirm)
+            .field("mark_days", &self.mark_days)
+            .field("include_tags", &self.include_tags)
+            .field("exclude_tags", &self.exclude_tags)
+            .field("scoring", &self.scoring)
+            .field("limits", &self.limits)
+            .field("retry", &self.retry)
             .field("aws", &self.aws)
             .finish()
     }
@@ -170,19 +731,42 @@ impl fmt::Debug for AppConfig {
             .field("name", &self.name)
             .field("run_option", &self.run_option)
             .field("days", &self.days)
+            .field("min_age_days", &self.min_age_days)
+            .field(
+                "disable_deletion_protection",
+                &self.disable_deletion_protection,
+            )
+            .field("archive_dir", &self.archive_dir)
+            .field("confirm", &self.confirm)
+            .field("mark_days", &self.mark_days)
+            .field("include_tags", &self.include_tags)
+            .field("exclude_tags", &self.exclude_tags)
+            .field("scoring", &self.scoring)
+            .field("limits", &self.limits)
+            .field("retry", &self.retry)
             .field("aws", &self.aws)
             .finish()
     }
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AwsConfig {
+    /// How long the credentials of each assumed role are valid. They are
+    /// refreshed before they expire, so this only bounds each session.
+    #[serde(default = "default_session_duration_seconds")]
+    pub session_duration_seconds: u64,
     pub accounts: Vec<AwsAccount>,
 }
 
+fn default_session_duration_seconds() -> u64 {
+    3600
+}
+
 impl fmt::Display for AwsConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("AwsConfig")
+            .field("session_duration_seconds", &self.session_duration_seconds)
             .field("accounts", &self.accounts)
             .finish()
     }
@@ -191,16 +775,44 @@ impl fmt::Display for AwsConfig {
 impl fmt::Debug for AwsConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("AwsConfig")
+            .field("session_duration_seconds", &self.session_duration_seconds)
             .field("accounts", &self.accounts)
             .finish()
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AwsAccount {
     pub iam_role: String,
     pub regions: Vec<String>,
-    pub vpc_ids: Vec<String>,
+    /// Only load balancers in one of these VPCs are considered for deletion.
+    /// `vpc_ids` is accepted as an older name for this list.
+    #[serde(default, alias = "vpc_ids")]
+    pub include_vpc_ids: Vec<String>,
+    /// Load balancers in any of these VPCs are never deleted.
+    #[serde(default)]
+    pub exclude_vpc_ids: Vec<String>,
+    /// Only load balancers in a VPC matching all of these are considered for
+    /// deletion.
+    #[serde(default)]
+    pub include_vpc_tags: Vec<TagSelector>,
+    /// Load balancers in a VPC matching any of these are never deleted.
+    #[serde(default)]
+    pub exclude_vpc_tags: Vec<TagSelector>,
+    /// Only load balancers whose name matches one of these are considered
+    /// for deletion.
+    #[serde(default)]
+    pub include_names: Vec<NamePattern>,
+    /// Load balancers whose name matches any of these are never deleted.
+    #[serde(default)]
+    pub exclude_names: Vec<NamePattern>,
+    /// Only ELBv2s whose ARN matches one of these are considered for deletion.
+    #[serde(default)]
+    pub include_arns: Vec<NamePattern>,
+    /// ELBv2s whose ARN matches any of these are never deleted.
+    #[serde(default)]
+    pub exclude_arns: Vec<NamePattern>,
 }
 
 impl fmt::Debug for AwsAccount {
@@ -208,7 +820,14 @@ impl fmt::Debug for AwsAccount {
         f.debug_struct("AwsAccount")
             .field("iam_role", &self.iam_role)
             .field("regions", &self.regions)
-            .field("vpc_ids", &self.vpc_ids)
+            .field("include_vpc_ids", &self.include_vpc_ids)
+            .field("exclude_vpc_ids", &self.exclude_vpc_ids)
+            .field("include_vpc_tags", &self.include_vpc_tags)
+            .field("exclude_vpc_tags", &self.exclude_vpc_tags)
+            .field("include_names", &self.include_names)
+            .field("exclude_names", &self.exclude_names)
+            .field("include_arns", &self.include_arns)
+            .field("exclude_arns", &self.exclude_arns)
             .finish()
     }
 }
@@ -218,7 +837,65 @@ impl fmt::Display for AwsAccount {
         f.debug_struct("AwsAccount")
             .field("iam_role", &self.iam_role)
             .field("regions", &self.regions)
-            .field("vpc_ids", &self.vpc_ids)
+            .field("include_vpc_ids", &self.include_vpc_ids)
+            .field("exclude_vpc_ids", &self.exclude_vpc_ids)
+            .field("include_vpc_tags", &self.include_vpc_tags)
+            .field("exclude_vpc_tags", &self.exclude_vpc_tags)
+            .field("include_names", &self.include_names)
+            .field("exclude_names", &self.exclude_names)
+            .field("include_arns", &self.include_arns)
+            .field("exclude_arns", &self.exclude_arns)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tag_selectors() {
+        let cases = [
+            ("env=prod", TagSelector::Equals("env".into(), "prod".into())),
+            (
+                "env!=prod",
+                TagSelector::NotEquals("env".into(), "prod".into()),
+            ),
+            ("env", TagSelector::Exists("env".into())),
+            ("!env", TagSelector::Absent("env".into())),
+            (" owner= ", TagSelector::Equals("owner".into(), "".into())),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(TagSelector::from_str(input), Ok(expected));
+        }
+    }
+
+    #[test]
+    fn rejects_selectors_without_a_key() {
+        for input in ["", "=prod", "!=prod", "!"] {
+            assert_eq!(
+                TagSelector::from_str(input),
+                Err(format!("invalid tag selector {:?}: missing tag key", input)),
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_negated_keys_with_a_value() {
+        assert_eq!(
+            TagSelector::from_str("!env=prod"),
+            Err("invalid tag selector \"!env=prod\": `!` can't be combined with a value, write \"env!=prod\" to select a tag with another value".to_string())
+        );
+        assert_eq!(
+            TagSelector::from_str("!env!=prod"),
+            Err("invalid tag selector \"!env!=prod\": `!` can't be combined with a value, write \"env!=prod\" to select a tag with another value".to_string())
+        );
+    }
+
+    #[test]
+    fn displays_tag_selectors_as_written() {
+        for input in ["env=prod", "env!=prod", "env", "!env"] {
+            assert_eq!(TagSelector::from_str(input).unwrap().to_string(), input);
+        }
+    }
+}
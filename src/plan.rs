@@ -0,0 +1,505 @@
+use crate::account::{self, AccountData};
+use crate::elb::ElbData;
+use crate::elbv2::ElbV2Data;
+use crate::error::Error;
+use crate::limits;
+use crate::models::{AppConfig, AwsAccount, LoadBalancerState, ScoringConfig, TagSelector};
+use crate::summary::RunSummary;
+use crate::utils;
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancerFamily {
+    Classic,
+    Elbv2,
+}
+
+impl fmt::Debug for LoadBalancerFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LoadBalancerFamily::Classic => write!(f, "Classic"),
+            LoadBalancerFamily::Elbv2 => write!(f, "Elbv2"),
+        }
+    }
+}
+
+impl fmt::Display for LoadBalancerFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LoadBalancerFamily::Classic => write!(f, "classic ELB"),
+            LoadBalancerFamily::Elbv2 => write!(f, "ELBv2"),
+        }
+    }
+}
+
+/// A reviewable list of load balancers to delete, written in `plan` mode and
+/// read back by `apply`.
+#[derive(Serialize, Deserialize)]
+pub struct Plan {
+    pub name: String,
+    pub created_at: String,
+    pub days: i64,
+    /// Hash of the config the plan was made with, see `planned_config_hash`.
+    /// Plans written before it was added have none and are refused.
+    #[serde(default)]
+    pub config_hash: String,
+    pub load_balancers: Vec<PlannedLoadBalancer>,
+}
+
+impl fmt::Debug for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plan")
+            .field("name", &self.name)
+            .field("created_at", &self.created_at)
+            .field("days", &self.days)
+            .field("config_hash", &self.config_hash)
+            .field("load_balancers", &self.load_balancers)
+            .finish()
+    }
+}
+
+/// A load balancer as it was when the plan was made. `id` is the name of a
+/// classic ELB or the ARN of an ELBv2.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlannedLoadBalancer {
+    pub account_id: String,
+    pub family: LoadBalancerFamily,
+    pub id: String,
+    pub region: String,
+    pub vpc_id: String,
+    pub lb_type: String,
+    pub created_time: String,
+    pub last_active_at: String,
+    pub idle_days: String,
+    pub evidence: Vec<String>,
+    pub tags: HashMap<String, String>,
+    pub config_hash: String,
+}
+
+impl fmt::Debug for PlannedLoadBalancer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlannedLoadBalancer")
+            .field("account_id", &self.account_id)
+            .field("family", &self.family)
+            .field("id", &self.id)
+            .field("region", &self.region)
+            .field("vpc_id", &self.vpc_id)
+            .field("lb_type", &self.lb_type)
+            .field("created_time", &self.created_time)
+            .field("last_active_at", &self.last_active_at)
+            .field("idle_days", &self.idle_days)
+            .field("evidence", &self.evidence)
+            .field("tags", &self.tags)
+            .field("config_hash", &self.config_hash)
+            .finish()
+    }
+}
+
+impl PlannedLoadBalancer {
+    pub fn from_elb(account_id: &str, elb: &ElbData) -> Self {
+        PlannedLoadBalancer {
+            account_id: account_id.to_string(),
+            family: LoadBalancerFamily::Classic,
+            id: elb.name.clone(),
+            region: elb.region.to_string(),
+            vpc_id: elb.vpc_id.clone(),
+            lb_type: "classic".to_string(),
+            created_time: utils::format_time(&elb.created_time),
+            last_active_at: utils::format_time(&elb.last_active_at),
+            idle_days: elb.idle_days.clone(),
+            evidence: elb.evidence.clone(),
+            tags: elb.tags.clone(),
+            config_hash: elb.config_hash.clone(),
+        }
+    }
+
+    pub fn from_elbv2(account_id: &str, elbv2: &ElbV2Data) -> Self {
+        PlannedLoadBalancer {
+            account_id: account_id.to_string(),
+            family: LoadBalancerFamily::Elbv2,
+            id: elbv2.arn.clone(),
+            region: elbv2.region.to_string(),
+            vpc_id: elbv2.vpc_id.clone(),
+            lb_type: elbv2.lb_type.clone(),
+            created_time: utils::format_time(&elbv2.created_time),
+            last_active_at: utils::format_time(&elbv2.last_active_at),
+            idle_days: elbv2.idle_days.clone(),
+            evidence: elbv2.evidence.clone(),
+            tags: elbv2.tags.clone(),
+            config_hash: elbv2.config_hash.clone(),
+        }
+    }
+}
+
+/// Builds the plan of every inactive load balancer found in `accounts`.
+pub fn build_plan(conf: &AppConfig, accounts: &[AccountData]) -> Plan {
+    let mut load_balancers: Vec<PlannedLoadBalancer> = vec![];
+
+    for account in accounts {
+        for elb in account.elbs.iter() {
+            if elb.state == LoadBalancerState::Inactive {
+                load_balancers.push(PlannedLoadBalancer::from_elb(&account.account_id, elb));
+            }
+        }
+        for elbv2 in account.elbv2s.iter() {
+            if elbv2.state == LoadBalancerState::Inactive {
+                load_balancers.push(PlannedLoadBalancer::from_elbv2(&account.account_id, elbv2));
+            }
+        }
+    }
+
+    Plan {
+        name: conf.name.clone(),
+        created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        days: conf.days,
+        config_hash: planned_config_hash(conf),
+        load_balancers,
+    }
+}
+
+/// The parts of the config that decide which load balancers are planned.
+/// Settings that only change how a run deletes, e.g. `confirm`, `limits` or
+/// `retry`, are left out so they can still be changed before `apply`.
+#[derive(Serialize)]
+struct PlannedConfig<'a> {
+    name: &'a str,
+    days: i64,
+    min_age_days: i64,
+    disable_deletion_protection: bool,
+    include_tags: &'a [TagSelector],
+    exclude_tags: &'a [TagSelector],
+    scoring: &'a ScoringConfig,
+    accounts: &'a [AwsAccount],
+}
+
+/// Hashes the parts of the config that decide which load balancers are
+/// planned, so that `apply` refuses a plan made under another config.
+pub fn planned_config_hash(conf: &AppConfig) -> String {
+    let planned = PlannedConfig {
+        name: &conf.name,
+        days: conf.days,
+        min_age_days: conf.min_age_days,
+        disable_deletion_protection: conf.disable_deletion_protection,
+        include_tags: &conf.include_tags,
+        exclude_tags: &conf.exclude_tags,
+        scoring: &conf.scoring,
+        accounts: &conf.aws.accounts,
+    };
+    utils::config_hash(&[serde_json::to_string(&planned).unwrap_or_default()])
+}
+
+pub fn write_plan(file_name: &str, plan: &Plan) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(plan).map_err(|e| e.to_string())?;
+    fs::write(file_name, contents).map_err(|e| e.to_string())
+}
+
+pub fn read_plan(file_name: &str) -> Result<Plan, String> {
+    let contents = fs::read_to_string(file_name).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+/// Returns why a planned load balancer must not be deleted any more, or
+/// `None` when it is still inactive and nothing about it changed.
+pub fn refusal_reason(
+    planned: &PlannedLoadBalancer,
+    current: Option<(&LoadBalancerState, PlannedLoadBalancer)>,
+) -> Option<String> {
+    let (state, current) = match current {
+        Some(current) => current,
+        None => return Some("no longer exists".to_string()),
+    };
+
+    if *state != LoadBalancerState::Inactive {
+        Some(format!("is now {}", state))
+    } else if current.config_hash != planned.config_hash {
+        Some("configuration changed".to_string())
+    } else if current.tags != planned.tags {
+        Some("tags changed".to_string())
+    } else if current.last_active_at != planned.last_active_at {
+        Some(format!(
+            "activity changed, last active at {}",
+            current.last_active_at
+        ))
+    } else {
+        None
+    }
+}
+
+/// Re-discovers every account of the plan and deletes the planned load
+/// balancers that are unchanged since the plan was made.
+pub async fn apply(conf: Arc<AppConfig>, plan_file: &str) -> Result<RunSummary, Error> {
+    let plan = read_plan(plan_file).map_err(|message| Error::File {
+        path: plan_file.to_string(),
+        message,
+    })?;
+    if plan.days != conf.days {
+        return Err(Error::Config(format!(
+            "plan {} was made with a {}-day lookback but the config has {}",
+            plan_file, plan.days, conf.days
+        )));
+    }
+    if plan.config_hash != planned_config_hash(&conf) {
+        return Err(Error::Config(format!(
+            "plan {} was made under another config, e.g. other scoring, filters or accounts, make a new plan",
+            plan_file
+        )));
+    }
+
+    let mut summary = RunSummary::default();
+
+    let account_ids = plan
+        .load_balancers
+        .iter()
+        .map(|planned| planned.account_id.clone())
+        .collect::<BTreeSet<String>>();
+
+    let mut tasks = Vec::new();
+    for account_id in account_ids {
+        let aws_account = conf.aws.accounts.iter().find(|aws_account| {
+            utils::extract_account_id_from_role_arn(&aws_account.iam_role).as_ref()
+                == Some(&account_id)
+        });
+        match aws_account {
+            Some(aws_account) => tasks.push((
+                account_id,
+                tokio::spawn(account::discover_account(
+                    Arc::clone(&conf),
+                    aws_account.clone(),
+                )),
+            )),
+            None => {
+                println!(
+                    "Refusing to delete load balancers of account {}: not in the config",
+                    account_id
+                );
+                let e = Error::Account {
+                    account_id: account_id.clone(),
+                    message: "not in the config".to_string(),
+                };
+                summary.add_errors(&account_id, vec![e]);
+            }
+        }
+    }
+
+    let mut discovered_accounts: Vec<AccountData> = vec![];
+    let mut approved_accounts: Vec<AccountData> = vec![];
+    for (account_id, task) in tasks {
+        let current = match task.await {
+            Ok(Ok(current)) => current,
+            Ok(Err(e)) if e.is_fatal() => return Err(e),
+            Ok(Err(e)) => {
+                println!("Refusing to delete load balancers: {}", e);
+                summary.add_errors(&account_id, vec![e]);
+                continue;
+            }
+            Err(e) => {
+                let e = Error::Account {
+                    account_id: account_id.clone(),
+                    message: format!("error discovering account: {}", e),
+                };
+                println!("Refusing to delete load balancers: {}", e);
+                summary.add_errors(&account_id, vec![e]);
+                continue;
+            }
+        };
+        summary.add_errors(&current.account_id, current.errors.clone());
+        let planned = plan
+            .load_balancers
+            .iter()
+            .filter(|planned| planned.account_id == current.account_id);
+
+        let mut approved = AccountData {
+            account_id: current.account_id.clone(),
+            credentials: current.credentials.clone(),
+            elbs: vec![],
+            elbv2s: vec![],
+            errors: vec![],
+        };
+        for planned in planned {
+            let reason = match planned.family {
+                LoadBalancerFamily::Classic => {
+                    let elb = current.elbs.iter().find(|elb| elb.name == planned.id);
+                    let reason = refusal_reason(
+                        planned,
+                        elb.map(|elb| {
+                            (
+                                &elb.state,
+                                PlannedLoadBalancer::from_elb(&current.account_id, elb),
+                            )
+                        }),
+                    );
+                    if let (None, Some(elb)) = (&reason, elb) {
+                        approved.elbs.push(elb.clone());
+                    }
+                    reason
+                }
+                LoadBalancerFamily::Elbv2 => {
+                    let elbv2 = current.elbv2s.iter().find(|elbv2| elbv2.arn == planned.id);
+                    let reason = refusal_reason(
+                        planned,
+                        elbv2.map(|elbv2| {
+                            (
+                                &elbv2.state,
+                                PlannedLoadBalancer::from_elbv2(&current.account_id, elbv2),
+                            )
+                        }),
+                    );
+                    if let (None, Some(elbv2)) = (&reason, elbv2) {
+                        approved.elbv2s.push(elbv2.clone());
+                    }
+                    reason
+                }
+            };
+            if let Some(reason) = reason {
+                println!(
+                    "Refusing to delete {} {}: {}",
+                    planned.family, planned.id, reason
+                );
+            }
+        }
+        approved_accounts.push(approved);
+        discovered_accounts.push(current);
+    }
+
+    limits::check_limits(&conf.limits, &approved_accounts, &discovered_accounts)?;
+    for account in approved_accounts {
+        let account_id = account.account_id.clone();
+        let deletions = account::delete_load_balancers(Arc::clone(&conf), account).await;
+        summary.add_deletions(&account_id, deletions);
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planned() -> PlannedLoadBalancer {
+        let mut tags = HashMap::new();
+        tags.insert("env".to_string(), "dev".to_string());
+        PlannedLoadBalancer {
+            account_id: "123456789012".to_string(),
+            family: LoadBalancerFamily::Elbv2,
+            id: "arn:aws:elasticloadbalancing:eu-west-1:123456789012:loadbalancer/app/web/1"
+                .to_string(),
+            region: "eu-west-1".to_string(),
+            vpc_id: "vpc-0123456789abcdef0".to_string(),
+            lb_type: "application".to_string(),
+            created_time: "2025-01-01T00:00:00Z".to_string(),
+            last_active_at: "2026-01-01T00:00:00Z".to_string(),
+            idle_days: "30".to_string(),
+            evidence: vec![],
+            tags,
+            config_hash: "abc".to_string(),
+        }
+    }
+
+    fn reason(state: LoadBalancerState, current: PlannedLoadBalancer) -> Option<String> {
+        refusal_reason(&planned(), Some((&state, current)))
+    }
+
+    #[test]
+    fn approves_unchanged_inactive_load_balancers() {
+        assert_eq!(reason(LoadBalancerState::Inactive, planned()), None);
+    }
+
+    #[test]
+    fn refuses_deleted_load_balancers() {
+        assert_eq!(
+            refusal_reason(&planned(), None),
+            Some("no longer exists".to_string())
+        );
+    }
+
+    #[test]
+    fn refuses_load_balancers_that_are_no_longer_inactive() {
+        let states = [
+            LoadBalancerState::Active,
+            LoadBalancerState::Unknown("throttled".to_string()),
+            LoadBalancerState::Skipped("deletion protection enabled".to_string()),
+        ];
+        for state in states {
+            let expected = format!("is now {}", state);
+            assert_eq!(reason(state, planned()), Some(expected));
+        }
+    }
+
+    #[test]
+    fn refuses_changed_load_balancers() {
+        let mut current = planned();
+        current.config_hash = "def".to_string();
+        assert_eq!(
+            reason(LoadBalancerState::Inactive, current),
+            Some("configuration changed".to_string())
+        );
+
+        let mut current = planned();
+        current
+            .tags
+            .insert("janitor:keep".to_string(), "true".to_string());
+        assert_eq!(
+            reason(LoadBalancerState::Inactive, current),
+            Some("tags changed".to_string())
+        );
+
+        let mut current = planned();
+        current.last_active_at = "2026-01-10T00:00:00Z".to_string();
+        assert_eq!(
+            reason(LoadBalancerState::Inactive, current),
+            Some("activity changed, last active at 2026-01-10T00:00:00Z".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_the_state_before_other_changes() {
+        let mut current = planned();
+        current.config_hash = "def".to_string();
+        current.tags.clear();
+        assert_eq!(
+            reason(LoadBalancerState::Active, current),
+            Some(format!("is now {}", LoadBalancerState::Active))
+        );
+    }
+
+    fn config(extra: &str) -> AppConfig {
+        let config = format!(
+            "name = \"janitor\"\nrun_option = \"plan\"\ndays = 14\n{}\n[[aws.accounts]]\niam_role = \"arn:aws:iam::123456789012:role/lb-janitor\"\nregions = [\"eu-west-1\"]\n",
+            extra
+        );
+        toml::from_str(&config).unwrap()
+    }
+
+    #[test]
+    fn config_hash_ignores_how_the_run_deletes() {
+        let planned = planned_config_hash(&config(""));
+        assert_eq!(
+            planned_config_hash(&config(
+                "confirm = true\nmark_days = 3\n[limits]\nmax_per_run = 1"
+            )),
+            planned
+        );
+    }
+
+    #[test]
+    fn config_hash_covers_what_is_planned() {
+        let planned = planned_config_hash(&config(""));
+        for extra in [
+            "min_age_days = 7",
+            "exclude_tags = [\"janitor:keep=true\"]",
+            "[scoring]\nactive_score = 2.0",
+        ] {
+            assert_ne!(planned_config_hash(&config(extra)), planned, "{}", extra);
+        }
+        let mut other_account = config("");
+        other_account.aws.accounts[0].exclude_names = vec!["k8s-*".parse().unwrap()];
+        assert_ne!(planned_config_hash(&other_account), planned);
+    }
+}
@@ -0,0 +1,764 @@
+use crate::error::Error;
+use crate::models::AppConfig;
+use crate::retry;
+use crate::snapshot::{
+    read_snapshot, ActionSnapshot, ClassicSnapshot, ConditionSnapshot, Elbv2Snapshot,
+    LoadBalancerSnapshot, TargetGroupSnapshot,
+};
+use crate::sts;
+use crate::utils;
+
+use aws_sdk_elasticloadbalancing::model::{
+    AccessLog, ConnectionDraining, ConnectionSettings, CrossZoneLoadBalancing,
+    HealthCheck as ClassicHealthCheck, Instance, Listener as ClassicListener,
+    LoadBalancerAttributes as ClassicAttributes, PolicyAttribute, Tag as ClassicTag,
+};
+use aws_sdk_elasticloadbalancing::Client as ELBClient;
+use aws_sdk_elasticloadbalancingv2::model::{
+    Action, ActionTypeEnum, Certificate, FixedResponseActionConfig, ForwardActionConfig,
+    HostHeaderConditionConfig, HttpHeaderConditionConfig, HttpRequestMethodConditionConfig,
+    IpAddressType, LoadBalancerAttribute, LoadBalancerSchemeEnum, LoadBalancerTypeEnum, Matcher,
+    PathPatternConditionConfig, ProtocolEnum, QueryStringConditionConfig, QueryStringKeyValuePair,
+    RedirectActionConfig, RedirectActionStatusCodeEnum, RuleCondition, SourceIpConditionConfig,
+    SubnetMapping, Tag, TargetDescription, TargetGroupAttribute, TargetGroupIpAddressTypeEnum,
+    TargetGroupStickinessConfig, TargetGroupTuple, TargetTypeEnum,
+};
+use aws_sdk_elasticloadbalancingv2::Client as ELBv2Client;
+use aws_types::region::Region;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Recreates the load balancer of a snapshot in the account and region it was
+/// taken in, and prints its new DNS name.
+pub async fn restore(conf: Arc<AppConfig>, snapshot_file: &str) -> Result<(), Error> {
+    let snapshot = read_snapshot(snapshot_file).map_err(|message| Error::File {
+        path: snapshot_file.to_string(),
+        message,
+    })?;
+
+    let aws_account = conf
+        .aws
+        .accounts
+        .iter()
+        .find(|aws_account| {
+            utils::extract_account_id_from_role_arn(&aws_account.iam_role).as_ref()
+                == Some(&snapshot.account_id)
+        })
+        .ok_or_else(|| {
+            Error::Config(format!(
+                "account {} of snapshot {} is not in the config",
+                snapshot.account_id, snapshot_file
+            ))
+        })?;
+
+    let account_error = |message: String| Error::Account {
+        account_id: snapshot.account_id.clone(),
+        message,
+    };
+    let credentials = sts::assume_role(&aws_account.iam_role, conf.aws.session_duration_seconds)
+        .await
+        .map_err(account_error)?;
+    sts::verify_account(&credentials, &snapshot.account_id)
+        .await
+        .map_err(|e| account_error(format!("not restoring: {}", e)))?;
+
+    let config = aws_config::from_env()
+        .retry_config(retry::sdk_retry_config())
+        .credentials_provider(credentials)
+        .region(Region::new(snapshot.region.clone()))
+        .load()
+        .await;
+
+    let name = snapshot.load_balancer.name().to_string();
+    let restored = match &snapshot.load_balancer {
+        LoadBalancerSnapshot::Classic(classic) => {
+            restore_elb(&ELBClient::new(&config), classic).await
+        }
+        LoadBalancerSnapshot::Elbv2(elbv2) => {
+            restore_elbv2(&ELBv2Client::new(&config), elbv2).await
+        }
+    };
+
+    let dns_name = restored.map_err(|e| Error::LoadBalancer {
+        id: name.clone(),
+        message: format!("error restoring: {}", e),
+    })?;
+    println!("Restored {}, new DNS name: {}", name, dns_name);
+    Ok(())
+}
+
+/// Recreates a classic ELB with its listeners, policies, health check,
+/// attributes, instances and tags, and returns its DNS name.
+async fn restore_elb(client: &ELBClient, snapshot: &ClassicSnapshot) -> Result<String, String> {
+    let mut created: Vec<String> = vec![];
+    restore_elb_resources(client, snapshot, &mut created)
+        .await
+        .map_err(|e| with_created(e, &created))
+}
+
+/// Appends what a failed restore already created, which has to be deleted
+/// before the restore can be retried under the same names.
+fn with_created(error: String, created: &[String]) -> String {
+    if created.is_empty() {
+        error
+    } else {
+        format!("{}, already created: {}", error, created.join(", "))
+    }
+}
+
+async fn restore_elb_resources(
+    client: &ELBClient,
+    snapshot: &ClassicSnapshot,
+    created: &mut Vec<String>,
+) -> Result<String, String> {
+    let listeners = snapshot
+        .listeners
+        .iter()
+        .map(|listener| {
+            ClassicListener::builder()
+                .protocol(&listener.protocol)
+                .load_balancer_port(listener.load_balancer_port)
+                .set_instance_protocol(listener.instance_protocol.clone())
+                .instance_port(listener.instance_port)
+                .set_ssl_certificate_id(listener.ssl_certificate_id.clone())
+                .build()
+        })
+        .collect::<Vec<ClassicListener>>();
+    let tags = snapshot
+        .tags
+        .iter()
+        .map(|(key, value)| ClassicTag::builder().key(key).value(value).build())
+        .collect::<Vec<ClassicTag>>();
+
+    // Load balancers in a VPC are placed by subnet, EC2-Classic ones by zone.
+    let (subnets, availability_zones) = if snapshot.subnets.is_empty() {
+        (None, Some(snapshot.availability_zones.clone()))
+    } else {
+        (Some(snapshot.subnets.clone()), None)
+    };
+    let scheme = match snapshot.scheme.as_str() {
+        "internal" => Some("internal".to_string()),
+        _ => None,
+    };
+    let security_groups = Some(snapshot.security_groups.clone()).filter(|sgs| !sgs.is_empty());
+
+    let resp = retry::send("ELB CreateLoadBalancer", || {
+        client
+            .create_load_balancer()
+            .load_balancer_name(&snapshot.name)
+            .set_listeners(Some(listeners.clone()))
+            .set_subnets(subnets.clone())
+            .set_availability_zones(availability_zones.clone())
+            .set_security_groups(security_groups.clone())
+            .set_scheme(scheme.clone())
+            .set_tags(Some(tags.clone()).filter(|tags| !tags.is_empty()))
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error creating load balancer: {}", e))?;
+    let dns_name = resp.dns_name().unwrap_or_default().to_string();
+    created.push(format!("classic ELB {}", snapshot.name));
+
+    for policy in snapshot.policies.iter() {
+        let attributes = policy
+            .attributes
+            .iter()
+            .map(|attribute| {
+                PolicyAttribute::builder()
+                    .attribute_name(&attribute.name)
+                    .attribute_value(&attribute.value)
+                    .build()
+            })
+            .collect::<Vec<PolicyAttribute>>();
+        retry::send("ELB CreateLoadBalancerPolicy", || {
+            client
+                .create_load_balancer_policy()
+                .load_balancer_name(&snapshot.name)
+                .policy_name(&policy.name)
+                .policy_type_name(&policy.type_name)
+                .set_policy_attributes(Some(attributes.clone()))
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error creating policy {}: {}", policy.name, e))?;
+    }
+    for listener in snapshot.listeners.iter() {
+        if listener.policy_names.is_empty() {
+            continue;
+        }
+        retry::send("ELB SetLoadBalancerPoliciesOfListener", || {
+            client
+                .set_load_balancer_policies_of_listener()
+                .load_balancer_name(&snapshot.name)
+                .load_balancer_port(listener.load_balancer_port)
+                .set_policy_names(Some(listener.policy_names.clone()))
+                .send()
+        })
+        .await
+        .map_err(|e| {
+            format!(
+                "error setting policies of listener {}: {}",
+                listener.load_balancer_port, e
+            )
+        })?;
+    }
+    for backend in snapshot.backend_servers.iter() {
+        retry::send("ELB SetLoadBalancerPoliciesForBackendServer", || {
+            client
+                .set_load_balancer_policies_for_backend_server()
+                .load_balancer_name(&snapshot.name)
+                .instance_port(backend.instance_port)
+                .set_policy_names(Some(backend.policy_names.clone()))
+                .send()
+        })
+        .await
+        .map_err(|e| {
+            format!(
+                "error setting policies of backend port {}: {}",
+                backend.instance_port, e
+            )
+        })?;
+    }
+
+    if let Some(health_check) = &snapshot.health_check {
+        retry::send("ELB ConfigureHealthCheck", || {
+            client
+                .configure_health_check()
+                .load_balancer_name(&snapshot.name)
+                .health_check(
+                    ClassicHealthCheck::builder()
+                        .target(&health_check.target)
+                        .interval(health_check.interval)
+                        .timeout(health_check.timeout)
+                        .unhealthy_threshold(health_check.unhealthy_threshold)
+                        .healthy_threshold(health_check.healthy_threshold)
+                        .build(),
+                )
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error configuring health check: {}", e))?;
+    }
+
+    let attributes = &snapshot.attributes;
+    let access_log = attributes.access_log.as_ref().map(|log| {
+        AccessLog::builder()
+            .enabled(log.enabled)
+            .set_s3_bucket_name(log.s3_bucket_name.clone())
+            .set_emit_interval(log.emit_interval)
+            .set_s3_bucket_prefix(log.s3_bucket_prefix.clone())
+            .build()
+    });
+    retry::send("ELB ModifyLoadBalancerAttributes", || {
+        client
+            .modify_load_balancer_attributes()
+            .load_balancer_name(&snapshot.name)
+            .load_balancer_attributes(
+                ClassicAttributes::builder()
+                    .cross_zone_load_balancing(
+                        CrossZoneLoadBalancing::builder()
+                            .enabled(attributes.cross_zone_load_balancing)
+                            .build(),
+                    )
+                    .connection_draining(
+                        ConnectionDraining::builder()
+                            .enabled(attributes.connection_draining_enabled)
+                            .set_timeout(attributes.connection_draining_timeout)
+                            .build(),
+                    )
+                    .set_connection_settings(attributes.idle_timeout.map(|idle_timeout| {
+                        ConnectionSettings::builder()
+                            .idle_timeout(idle_timeout)
+                            .build()
+                    }))
+                    .set_access_log(access_log.clone())
+                    .build(),
+            )
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error setting attributes: {}", e))?;
+
+    if !snapshot.instances.is_empty() {
+        let instances = snapshot
+            .instances
+            .iter()
+            .map(|id| Instance::builder().instance_id(id).build())
+            .collect::<Vec<Instance>>();
+        retry::send("ELB RegisterInstancesWithLoadBalancer", || {
+            client
+                .register_instances_with_load_balancer()
+                .load_balancer_name(&snapshot.name)
+                .set_instances(Some(instances.clone()))
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error registering instances: {}", e))?;
+    }
+
+    Ok(dns_name)
+}
+
+/// Recreates an ALB or NLB with its target groups, listeners, rules,
+/// attributes and tags, and returns its DNS name. Target groups that still
+/// exist are reused.
+async fn restore_elbv2(client: &ELBv2Client, snapshot: &Elbv2Snapshot) -> Result<String, String> {
+    check_elbv2_restorable(snapshot)?;
+
+    let mut created: Vec<String> = vec![];
+    restore_elbv2_resources(client, snapshot, &mut created)
+        .await
+        .map_err(|e| with_created(e, &created))
+}
+
+/// Fails with every part of the snapshot that can't be restored, before
+/// anything is created.
+fn check_elbv2_restorable(snapshot: &Elbv2Snapshot) -> Result<(), String> {
+    let mut problems: Vec<String> = vec![];
+    if snapshot.lb_type == "gateway" {
+        problems.push("restoring gateway load balancers is not supported".to_string());
+    }
+    for listener in snapshot.listeners.iter() {
+        let actions = listener
+            .default_actions
+            .iter()
+            .map(|action| (&listener.arn, action));
+        let rule_actions = listener
+            .rules
+            .iter()
+            .flat_map(|rule| rule.actions.iter().map(move |action| (&rule.arn, action)));
+        for (arn, action) in actions.chain(rule_actions) {
+            if action.action_type.starts_with("authenticate") {
+                problems.push(format!(
+                    "{} action of {} can't be restored, recreate it by hand",
+                    action.action_type, arn
+                ));
+            }
+        }
+        for rule in listener.rules.iter() {
+            if let Err(e) = rule.priority.parse::<i32>() {
+                problems.push(format!("invalid priority of rule {}: {}", rule.arn, e));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("; "))
+    }
+}
+
+async fn restore_elbv2_resources(
+    client: &ELBv2Client,
+    snapshot: &Elbv2Snapshot,
+    created: &mut Vec<String>,
+) -> Result<String, String> {
+    // Old target group ARN to the ARN of the target group in use now.
+    let mut tg_arns: HashMap<String, String> = HashMap::new();
+    for tg in snapshot.target_groups.iter() {
+        let tg_arn = restore_target_group(client, tg, created).await?;
+        tg_arns.insert(tg.arn.clone(), tg_arn);
+    }
+
+    let subnet_mappings = snapshot
+        .subnets
+        .iter()
+        .map(|subnet| {
+            SubnetMapping::builder()
+                .subnet_id(&subnet.subnet_id)
+                .set_allocation_id(subnet.allocation_id.clone())
+                .set_private_i_pv4_address(subnet.private_ipv4_address.clone())
+                .set_i_pv6_address(subnet.ipv6_address.clone())
+                .build()
+        })
+        .collect::<Vec<SubnetMapping>>();
+    let security_groups = Some(snapshot.security_groups.clone()).filter(|sgs| !sgs.is_empty());
+
+    let resp = retry::send("ELBv2 CreateLoadBalancer", || {
+        client
+            .create_load_balancer()
+            .name(&snapshot.name)
+            .r#type(LoadBalancerTypeEnum::from(snapshot.lb_type.as_str()))
+            .scheme(LoadBalancerSchemeEnum::from(snapshot.scheme.as_str()))
+            .ip_address_type(IpAddressType::from(snapshot.ip_address_type.as_str()))
+            .set_subnet_mappings(Some(subnet_mappings.clone()))
+            .set_security_groups(security_groups.clone())
+            .set_tags(elbv2_tags(&snapshot.tags))
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error creating load balancer: {}", e))?;
+    let lb = resp
+        .load_balancers()
+        .unwrap_or_default()
+        .first()
+        .ok_or_else(|| "load balancer was not returned".to_string())?;
+    let lb_arn = lb.load_balancer_arn().unwrap_or_default().to_string();
+    let dns_name = lb.dns_name().unwrap_or_default().to_string();
+    println!("Created {}", lb_arn);
+    created.push(format!("load balancer {}", lb_arn));
+
+    let attributes = snapshot
+        .attributes
+        .iter()
+        .map(|(key, value)| {
+            LoadBalancerAttribute::builder()
+                .key(key)
+                .value(value)
+                .build()
+        })
+        .collect::<Vec<LoadBalancerAttribute>>();
+    if !attributes.is_empty() {
+        retry::send("ELBv2 ModifyLoadBalancerAttributes", || {
+            client
+                .modify_load_balancer_attributes()
+                .load_balancer_arn(&lb_arn)
+                .set_attributes(Some(attributes.clone()))
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error setting attributes: {}", e))?;
+    }
+
+    for listener in snapshot.listeners.iter() {
+        let default_certificates = listener
+            .certificates
+            .iter()
+            .filter(|certificate| certificate.is_default)
+            .map(|certificate| {
+                Certificate::builder()
+                    .certificate_arn(&certificate.arn)
+                    .build()
+            })
+            .collect::<Vec<Certificate>>();
+        let alpn_policy = Some(listener.alpn_policy.clone()).filter(|alpn| !alpn.is_empty());
+        let default_actions = elbv2_actions(&listener.default_actions, &tg_arns);
+
+        let resp = retry::send("ELBv2 CreateListener", || {
+            client
+                .create_listener()
+                .load_balancer_arn(&lb_arn)
+                .set_port(listener.port)
+                .set_protocol(listener.protocol.as_deref().map(ProtocolEnum::from))
+                .set_ssl_policy(listener.ssl_policy.clone())
+                .set_certificates(Some(default_certificates.clone()).filter(|c| !c.is_empty()))
+                .set_alpn_policy(alpn_policy.clone())
+                .set_default_actions(Some(default_actions.clone()))
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error creating listener {}: {}", listener.arn, e))?;
+        let listener_arn = resp
+            .listeners()
+            .unwrap_or_default()
+            .first()
+            .and_then(|listener| listener.listener_arn())
+            .unwrap_or_default()
+            .to_string();
+
+        let extra_certificates = listener
+            .certificates
+            .iter()
+            .filter(|certificate| !certificate.is_default)
+            .map(|certificate| {
+                Certificate::builder()
+                    .certificate_arn(&certificate.arn)
+                    .build()
+            })
+            .collect::<Vec<Certificate>>();
+        if !extra_certificates.is_empty() {
+            retry::send("ELBv2 AddListenerCertificates", || {
+                client
+                    .add_listener_certificates()
+                    .listener_arn(&listener_arn)
+                    .set_certificates(Some(extra_certificates.clone()))
+                    .send()
+            })
+            .await
+            .map_err(|e| format!("error adding certificates: {}", e))?;
+        }
+
+        for rule in listener.rules.iter() {
+            // Checked by check_elbv2_restorable.
+            let priority = rule.priority.parse::<i32>().unwrap_or_default();
+            let actions = elbv2_actions(&rule.actions, &tg_arns);
+            retry::send("ELBv2 CreateRule", || {
+                client
+                    .create_rule()
+                    .listener_arn(&listener_arn)
+                    .priority(priority)
+                    .set_conditions(Some(rule.conditions.iter().map(elbv2_condition).collect()))
+                    .set_actions(Some(actions.clone()))
+                    .send()
+            })
+            .await
+            .map_err(|e| format!("error creating rule {}: {}", rule.arn, e))?;
+        }
+    }
+
+    Ok(dns_name)
+}
+
+/// Returns the ARN of the target group, creating it with its attributes and
+/// tags if it no longer exists, and registers its targets.
+async fn restore_target_group(
+    client: &ELBv2Client,
+    tg: &TargetGroupSnapshot,
+    created: &mut Vec<String>,
+) -> Result<String, String> {
+    let existing = retry::send("ELBv2 DescribeTargetGroups", || {
+        client
+            .describe_target_groups()
+            .target_group_arns(&tg.arn)
+            .send()
+    })
+    .await
+    .ok()
+    .and_then(|resp| {
+        resp.target_groups()
+            .unwrap_or_default()
+            .first()
+            .and_then(|tg| tg.target_group_arn())
+            .map(|arn| arn.to_string())
+    });
+
+    let tg_arn = match existing {
+        Some(tg_arn) => {
+            println!("Reusing target group {}", tg_arn);
+            tg_arn
+        }
+        None => {
+            let health_check = &tg.health_check;
+            let matcher = match (
+                &health_check.matcher_http_code,
+                &health_check.matcher_grpc_code,
+            ) {
+                (None, None) => None,
+                (http_code, grpc_code) => Some(
+                    Matcher::builder()
+                        .set_http_code(http_code.clone())
+                        .set_grpc_code(grpc_code.clone())
+                        .build(),
+                ),
+            };
+            let resp = retry::send("ELBv2 CreateTargetGroup", || {
+                client
+                    .create_target_group()
+                    .name(&tg.name)
+                    .set_protocol(tg.protocol.as_deref().map(ProtocolEnum::from))
+                    .set_protocol_version(tg.protocol_version.clone())
+                    .set_port(tg.port)
+                    .set_vpc_id(tg.vpc_id.clone())
+                    .target_type(TargetTypeEnum::from(tg.target_type.as_str()))
+                    .set_ip_address_type(
+                        tg.ip_address_type
+                            .as_deref()
+                            .map(TargetGroupIpAddressTypeEnum::from),
+                    )
+                    .set_health_check_enabled(health_check.enabled)
+                    .set_health_check_protocol(
+                        health_check.protocol.as_deref().map(ProtocolEnum::from),
+                    )
+                    .set_health_check_port(health_check.port.clone())
+                    .set_health_check_path(health_check.path.clone())
+                    .set_health_check_interval_seconds(health_check.interval_seconds)
+                    .set_health_check_timeout_seconds(health_check.timeout_seconds)
+                    .set_healthy_threshold_count(health_check.healthy_threshold_count)
+                    .set_unhealthy_threshold_count(health_check.unhealthy_threshold_count)
+                    .set_matcher(matcher.clone())
+                    .set_tags(elbv2_tags(&tg.tags))
+                    .send()
+            })
+            .await
+            .map_err(|e| format!("error creating target group {}: {}", tg.name, e))?;
+            let tg_arn = resp
+                .target_groups()
+                .unwrap_or_default()
+                .first()
+                .and_then(|tg| tg.target_group_arn())
+                .unwrap_or_default()
+                .to_string();
+            println!("Created target group {}", tg_arn);
+            created.push(format!("target group {}", tg_arn));
+
+            let attributes = tg
+                .attributes
+                .iter()
+                .map(|(key, value)| {
+                    TargetGroupAttribute::builder()
+                        .key(key)
+                        .value(value)
+                        .build()
+                })
+                .collect::<Vec<TargetGroupAttribute>>();
+            if !attributes.is_empty() {
+                retry::send("ELBv2 ModifyTargetGroupAttributes", || {
+                    client
+                        .modify_target_group_attributes()
+                        .target_group_arn(&tg_arn)
+                        .set_attributes(Some(attributes.clone()))
+                        .send()
+                })
+                .await
+                .map_err(|e| format!("error setting attributes of {}: {}", tg.name, e))?;
+            }
+            tg_arn
+        }
+    };
+
+    if !tg.targets.is_empty() {
+        let targets = tg
+            .targets
+            .iter()
+            .map(|target| {
+                TargetDescription::builder()
+                    .id(&target.id)
+                    .set_port(target.port)
+                    .set_availability_zone(target.availability_zone.clone())
+                    .build()
+            })
+            .collect::<Vec<TargetDescription>>();
+        retry::send("ELBv2 RegisterTargets", || {
+            client
+                .register_targets()
+                .target_group_arn(&tg_arn)
+                .set_targets(Some(targets.clone()))
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error registering targets of {}: {}", tg.name, e))?;
+    }
+
+    Ok(tg_arn)
+}
+
+fn elbv2_tags(tags: &BTreeMap<String, String>) -> Option<Vec<Tag>> {
+    if tags.is_empty() {
+        return None;
+    }
+    Some(
+        tags.iter()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect(),
+    )
+}
+
+/// Rebuilds actions, pointing them at the target groups in use now.
+/// Authentication actions are rejected by `check_elbv2_restorable`.
+fn elbv2_actions(actions: &[ActionSnapshot], tg_arns: &HashMap<String, String>) -> Vec<Action> {
+    let tg_arn = |arn: &String| tg_arns.get(arn).cloned().unwrap_or_else(|| arn.clone());
+
+    let mut restored: Vec<Action> = vec![];
+    for action in actions {
+        let forward_target_groups = action
+            .forward_target_groups
+            .iter()
+            .map(|tg| {
+                TargetGroupTuple::builder()
+                    .target_group_arn(tg_arn(&tg.target_group_arn))
+                    .set_weight(tg.weight)
+                    .build()
+            })
+            .collect::<Vec<TargetGroupTuple>>();
+        let forward_config = Some(forward_target_groups)
+            .filter(|tgs| !tgs.is_empty())
+            .map(|tgs| {
+                ForwardActionConfig::builder()
+                    .set_target_groups(Some(tgs))
+                    .set_target_group_stickiness_config(action.forward_stickiness.as_ref().map(
+                        |stickiness| {
+                            TargetGroupStickinessConfig::builder()
+                                .set_enabled(stickiness.enabled)
+                                .set_duration_seconds(stickiness.duration_seconds)
+                                .build()
+                        },
+                    ))
+                    .build()
+            });
+        let redirect_config = action.redirect.as_ref().map(|redirect| {
+            RedirectActionConfig::builder()
+                .set_protocol(redirect.protocol.clone())
+                .set_port(redirect.port.clone())
+                .set_host(redirect.host.clone())
+                .set_path(redirect.path.clone())
+                .set_query(redirect.query.clone())
+                .status_code(RedirectActionStatusCodeEnum::from(
+                    redirect.status_code.as_str(),
+                ))
+                .build()
+        });
+        let fixed_response_config = action.fixed_response.as_ref().map(|response| {
+            FixedResponseActionConfig::builder()
+                .status_code(&response.status_code)
+                .set_content_type(response.content_type.clone())
+                .set_message_body(response.message_body.clone())
+                .build()
+        });
+
+        restored.push(
+            Action::builder()
+                .r#type(ActionTypeEnum::from(action.action_type.as_str()))
+                .set_order(action.order)
+                .set_target_group_arn(action.target_group_arn.as_ref().map(tg_arn))
+                .set_forward_config(forward_config)
+                .set_redirect_config(redirect_config)
+                .set_fixed_response_config(fixed_response_config)
+                .build(),
+        );
+    }
+
+    restored
+}
+
+fn elbv2_condition(condition: &ConditionSnapshot) -> RuleCondition {
+    let values = Some(condition.values.clone());
+    let builder = RuleCondition::builder().field(&condition.field);
+
+    match condition.field.as_str() {
+        "host-header" => builder.host_header_config(
+            HostHeaderConditionConfig::builder()
+                .set_values(values)
+                .build(),
+        ),
+        "path-pattern" => builder.path_pattern_config(
+            PathPatternConditionConfig::builder()
+                .set_values(values)
+                .build(),
+        ),
+        "http-header" => builder.http_header_config(
+            HttpHeaderConditionConfig::builder()
+                .set_http_header_name(condition.http_header_name.clone())
+                .set_values(values)
+                .build(),
+        ),
+        "http-request-method" => builder.http_request_method_config(
+            HttpRequestMethodConditionConfig::builder()
+                .set_values(values)
+                .build(),
+        ),
+        "source-ip" => builder.source_ip_config(
+            SourceIpConditionConfig::builder()
+                .set_values(values)
+                .build(),
+        ),
+        "query-string" => builder.query_string_config(
+            QueryStringConditionConfig::builder()
+                .set_values(Some(
+                    condition
+                        .query_strings
+                        .iter()
+                        .map(|pair| {
+                            QueryStringKeyValuePair::builder()
+                                .set_key(pair.key.clone())
+                                .value(&pair.value)
+                                .build()
+                        })
+                        .collect(),
+                ))
+                .build(),
+        ),
+        _ => builder.set_values(values),
+    }
+    .build()
+}
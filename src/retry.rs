@@ -0,0 +1,119 @@
+use crate::models::RetryPolicy;
+
+use aws_smithy_http::result::SdkError;
+use aws_smithy_types::retry::{ProvideErrorKind, RetryConfig};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+static COUNTS: Mutex<BTreeMap<String, RetryCount>> = Mutex::new(BTreeMap::new());
+
+/// How often the calls of one operation were retried during the run, and how
+/// many of them still failed once they ran out of attempts.
+#[derive(Clone, Default)]
+pub struct RetryCount {
+    pub retries: usize,
+    pub exhausted: usize,
+}
+
+impl fmt::Debug for RetryCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryCount")
+            .field("retries", &self.retries)
+            .field("exhausted", &self.exhausted)
+            .finish()
+    }
+}
+
+/// Sets the retry policy of the run. Calls made before it is set use the
+/// default policy.
+pub fn init(policy: RetryPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+fn policy() -> &'static RetryPolicy {
+    POLICY.get_or_init(RetryPolicy::default)
+}
+
+/// Retries are done by `send`, so the SDK's own retries are turned off on
+/// every client to not multiply the attempts.
+pub fn sdk_retry_config() -> RetryConfig {
+    RetryConfig::disabled()
+}
+
+/// Retry counts of every operation that was retried, keyed by operation.
+pub fn counts() -> BTreeMap<String, RetryCount> {
+    COUNTS.lock().unwrap().clone()
+}
+
+fn record(operation: &str, update: impl FnOnce(&mut RetryCount)) {
+    let mut counts = COUNTS.lock().unwrap();
+    update(counts.entry(operation.to_string()).or_default());
+}
+
+fn is_retryable<E: ProvideErrorKind>(error: &SdkError<E>, policy: &RetryPolicy) -> bool {
+    match error {
+        SdkError::ServiceError(e) => match e.err().code() {
+            Some(code) => policy.retry_errors.iter().any(|retried| retried == code),
+            None => false,
+        },
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            policy.retry_timeouts
+        }
+        _ => false,
+    }
+}
+
+/// Exponential backoff from `base_delay_ms`, capped at `max_delay_ms`, with
+/// `jitter` of the delay randomized.
+fn backoff(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let delay = policy
+        .base_delay_ms
+        .saturating_mul(2u64.saturating_pow(attempt - 1))
+        .min(policy.max_delay_ms) as f64;
+    let delay = delay * (1.0 - policy.jitter * fastrand::f64());
+    Duration::from_millis(delay as u64)
+}
+
+/// Sends an AWS request, retrying it under the run's retry policy. `request`
+/// builds and sends the request again on every attempt. `operation` names it
+/// in logs and in the run summary, e.g. `ELBv2 DescribeLoadBalancers`.
+pub async fn send<T, E, F, Fut>(operation: &str, request: F) -> Result<T, SdkError<E>>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, SdkError<E>>>,
+    E: ProvideErrorKind,
+    SdkError<E>: fmt::Display,
+{
+    let policy = policy();
+    let mut attempt = 1;
+
+    loop {
+        match request().await {
+            Err(e) if is_retryable(&e, policy) => {
+                if attempt >= policy.max_attempts {
+                    if attempt > 1 {
+                        record(operation, |count| count.exhausted += 1);
+                    }
+                    return Err(e);
+                }
+                let delay = backoff(policy, attempt);
+                eprintln!(
+                    "Retrying {} in {}ms, attempt {} of {} failed: {}",
+                    operation,
+                    delay.as_millis(),
+                    attempt,
+                    policy.max_attempts,
+                    e
+                );
+                record(operation, |count| count.retries += 1);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
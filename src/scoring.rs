@@ -0,0 +1,137 @@
+use crate::models::{LoadBalancerState, MetricRule, ScoringConfig};
+use crate::utils;
+
+use aws_sdk_cloudwatch::model::MetricDataResult;
+use chrono::{DateTime, Utc};
+
+/// Returns the weight contributed by `rule` for the given datapoints. The
+/// datapoints of every series queried for the rule (e.g. one per target group)
+/// are summed before being compared against the threshold.
+pub fn score_metric(rule: &MetricRule, values: &[f64]) -> f64 {
+    let total: f64 = values.iter().sum();
+    if total > rule.threshold {
+        rule.weight
+    } else {
+        0.0
+    }
+}
+
+/// Describes what `rule` saw over the lookback window, e.g.
+/// `LoadBalancer RequestCount Sum = 0 (threshold 0, weight 1)`.
+pub fn evidence(rule: &MetricRule, values: &[f64]) -> String {
+    let total: f64 = values.iter().sum();
+    format!(
+        "{} {} {} = {} (threshold {}, weight {})",
+        rule.dimension, rule.metric_name, rule.stat, total, rule.threshold, rule.weight
+    )
+}
+
+/// Returns the latest timestamp with a non-zero datapoint. Only rules that are
+/// enough to make a load balancer active on their own count as activity, so
+/// e.g. healthy targets on an idle load balancer don't reset its idle time.
+pub fn last_active_at(
+    rule: &MetricRule,
+    result: &MetricDataResult,
+    scoring: &ScoringConfig,
+) -> Option<DateTime<Utc>> {
+    if !is_active_score(rule.weight, scoring) {
+        return None;
+    }
+
+    let timestamps = result.timestamps().unwrap_or_default();
+    let values = result.values().unwrap_or_default();
+
+    timestamps
+        .iter()
+        .zip(values.iter())
+        .filter(|(_, value)| **value > 0.0)
+        .filter_map(|(timestamp, _)| utils::to_chrono_time(Some(timestamp)))
+        .max()
+}
+
+pub fn is_active_score(score: f64, scoring: &ScoringConfig) -> bool {
+    score >= scoring.active_score
+}
+
+/// An active score is conclusive even if some metrics failed to load, but an
+/// inactive one is only trusted when every metric was read successfully.
+pub fn state_from_score(
+    score: f64,
+    failures: &[String],
+    scoring: &ScoringConfig,
+) -> LoadBalancerState {
+    if is_active_score(score, scoring) {
+        LoadBalancerState::Active
+    } else if !failures.is_empty() {
+        LoadBalancerState::Unknown(failures.join("; "))
+    } else {
+        LoadBalancerState::Inactive
+    }
+}
+
+pub fn rules_for_namespace<'a>(
+    namespace: &str,
+    scoring: &'a ScoringConfig,
+) -> Option<&'a [MetricRule]> {
+    match namespace {
+        "AWS/ELB" => Some(&scoring.classic),
+        "AWS/ApplicationELB" => Some(&scoring.application),
+        "AWS/NetworkELB" => Some(&scoring.network),
+        "AWS/GatewayELB" => Some(&scoring.gateway),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failures(messages: &[&str]) -> Vec<String> {
+        messages.iter().map(|message| message.to_string()).collect()
+    }
+
+    #[test]
+    fn active_score_is_conclusive_despite_failures() {
+        let scoring = ScoringConfig::default();
+        assert_eq!(
+            state_from_score(1.0, &failures(&["HealthyHostCount: throttled"]), &scoring),
+            LoadBalancerState::Active
+        );
+    }
+
+    #[test]
+    fn never_inactive_when_a_metric_failed() {
+        let scoring = ScoringConfig::default();
+        assert_eq!(
+            state_from_score(
+                0.0,
+                &failures(&["RequestCount: throttled", "ProcessedBytes: partial data"]),
+                &scoring
+            ),
+            LoadBalancerState::Unknown(
+                "RequestCount: throttled; ProcessedBytes: partial data".to_string()
+            )
+        );
+        assert_eq!(
+            state_from_score(0.99, &failures(&["RequestCount: throttled"]), &scoring),
+            LoadBalancerState::Unknown("RequestCount: throttled".to_string())
+        );
+    }
+
+    #[test]
+    fn inactive_when_every_metric_was_read_below_the_active_score() {
+        let scoring = ScoringConfig::default();
+        assert_eq!(
+            state_from_score(0.0, &[], &scoring),
+            LoadBalancerState::Inactive
+        );
+        assert_eq!(
+            state_from_score(0.99, &[], &scoring),
+            LoadBalancerState::Inactive
+        );
+        assert_eq!(
+            state_from_score(1.0, &[], &scoring),
+            LoadBalancerState::Active
+        );
+    }
+}
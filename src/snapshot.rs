@@ -0,0 +1,1034 @@
+use crate::elb::get_elb_tags;
+use crate::elbv2::get_elbv2_tags;
+use crate::retry;
+
+use aws_sdk_elasticloadbalancing::Client as ELBClient;
+use aws_sdk_elasticloadbalancingv2::model::{
+    Action, Listener, RuleCondition, TargetGroup as TargetGroupV2,
+};
+use aws_sdk_elasticloadbalancingv2::Client as ELBv2Client;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// A backup of a load balancer, written to the archive directory before it
+/// is deleted. It holds everything needed to rebuild the load balancer.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken_at: String,
+    pub account_id: String,
+    pub region: String,
+    pub load_balancer: LoadBalancerSnapshot,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "family", rename_all = "snake_case")]
+pub enum LoadBalancerSnapshot {
+    Classic(ClassicSnapshot),
+    Elbv2(Elbv2Snapshot),
+}
+
+impl LoadBalancerSnapshot {
+    pub fn name(&self) -> &str {
+        match self {
+            LoadBalancerSnapshot::Classic(classic) => &classic.name,
+            LoadBalancerSnapshot::Elbv2(elbv2) => &elbv2.name,
+        }
+    }
+
+    /// `classic` or `elbv2`, as a classic ELB and an ELBv2 may share a name.
+    pub fn family(&self) -> &'static str {
+        match self {
+            LoadBalancerSnapshot::Classic(_) => "classic",
+            LoadBalancerSnapshot::Elbv2(_) => "elbv2",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClassicSnapshot {
+    pub name: String,
+    pub dns_name: String,
+    pub scheme: String,
+    pub vpc_id: Option<String>,
+    pub availability_zones: Vec<String>,
+    pub subnets: Vec<String>,
+    pub security_groups: Vec<String>,
+    pub listeners: Vec<ClassicListenerSnapshot>,
+    pub backend_servers: Vec<ClassicBackendServerSnapshot>,
+    pub policies: Vec<ClassicPolicySnapshot>,
+    pub health_check: Option<ClassicHealthCheckSnapshot>,
+    pub instances: Vec<String>,
+    pub attributes: ClassicAttributesSnapshot,
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClassicListenerSnapshot {
+    pub protocol: String,
+    pub load_balancer_port: i32,
+    pub instance_protocol: Option<String>,
+    pub instance_port: i32,
+    pub ssl_certificate_id: Option<String>,
+    pub policy_names: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClassicBackendServerSnapshot {
+    pub instance_port: i32,
+    pub policy_names: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClassicPolicySnapshot {
+    pub name: String,
+    pub type_name: String,
+    pub attributes: Vec<AttributeSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AttributeSnapshot {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClassicHealthCheckSnapshot {
+    pub target: String,
+    pub interval: i32,
+    pub timeout: i32,
+    pub unhealthy_threshold: i32,
+    pub healthy_threshold: i32,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ClassicAttributesSnapshot {
+    pub cross_zone_load_balancing: bool,
+    pub connection_draining_enabled: bool,
+    pub connection_draining_timeout: Option<i32>,
+    pub idle_timeout: Option<i32>,
+    pub access_log: Option<AccessLogSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AccessLogSnapshot {
+    pub enabled: bool,
+    pub s3_bucket_name: Option<String>,
+    pub emit_interval: Option<i32>,
+    pub s3_bucket_prefix: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Elbv2Snapshot {
+    pub arn: String,
+    pub name: String,
+    pub dns_name: String,
+    pub lb_type: String,
+    pub scheme: String,
+    pub ip_address_type: String,
+    pub vpc_id: String,
+    pub subnets: Vec<SubnetMappingSnapshot>,
+    pub security_groups: Vec<String>,
+    pub attributes: BTreeMap<String, String>,
+    pub tags: BTreeMap<String, String>,
+    pub listeners: Vec<ListenerSnapshot>,
+    pub target_groups: Vec<TargetGroupSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SubnetMappingSnapshot {
+    pub subnet_id: String,
+    pub allocation_id: Option<String>,
+    pub private_ipv4_address: Option<String>,
+    pub ipv6_address: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ListenerSnapshot {
+    pub arn: String,
+    pub port: Option<i32>,
+    pub protocol: Option<String>,
+    pub ssl_policy: Option<String>,
+    pub certificates: Vec<CertificateSnapshot>,
+    pub alpn_policy: Vec<String>,
+    pub default_actions: Vec<ActionSnapshot>,
+    /// Every rule except the default one, whose actions are `default_actions`.
+    pub rules: Vec<RuleSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CertificateSnapshot {
+    pub arn: String,
+    pub is_default: bool,
+}
+
+/// A listener or rule action.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ActionSnapshot {
+    pub action_type: String,
+    pub order: Option<i32>,
+    pub target_group_arn: Option<String>,
+    pub forward_target_groups: Vec<WeightedTargetGroupSnapshot>,
+    pub forward_stickiness: Option<StickinessSnapshot>,
+    pub redirect: Option<RedirectSnapshot>,
+    pub fixed_response: Option<FixedResponseSnapshot>,
+    pub authenticate_oidc: Option<AuthenticateOidcSnapshot>,
+    pub authenticate_cognito: Option<AuthenticateCognitoSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WeightedTargetGroupSnapshot {
+    pub target_group_arn: String,
+    pub weight: Option<i32>,
+}
+
+/// Target group stickiness of a `forward` action with several target groups.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StickinessSnapshot {
+    pub enabled: Option<bool>,
+    pub duration_seconds: Option<i32>,
+}
+
+/// An `authenticate-oidc` action. The client secret is not returned by the
+/// API, so `client_secret_required` records that it has to be supplied again
+/// when the action is recreated.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuthenticateOidcSnapshot {
+    pub issuer: Option<String>,
+    pub authorization_endpoint: Option<String>,
+    pub token_endpoint: Option<String>,
+    pub user_info_endpoint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret_required: bool,
+    pub session_cookie_name: Option<String>,
+    pub scope: Option<String>,
+    pub session_timeout: Option<i64>,
+    pub authentication_request_extra_params: BTreeMap<String, String>,
+    pub on_unauthenticated_request: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuthenticateCognitoSnapshot {
+    pub user_pool_arn: Option<String>,
+    pub user_pool_client_id: Option<String>,
+    pub user_pool_domain: Option<String>,
+    pub session_cookie_name: Option<String>,
+    pub scope: Option<String>,
+    pub session_timeout: Option<i64>,
+    pub authentication_request_extra_params: BTreeMap<String, String>,
+    pub on_unauthenticated_request: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RedirectSnapshot {
+    pub protocol: Option<String>,
+    pub port: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub query: Option<String>,
+    pub status_code: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FixedResponseSnapshot {
+    pub status_code: String,
+    pub content_type: Option<String>,
+    pub message_body: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RuleSnapshot {
+    pub arn: String,
+    pub priority: String,
+    pub conditions: Vec<ConditionSnapshot>,
+    pub actions: Vec<ActionSnapshot>,
+}
+
+/// A rule condition. `values` holds the values of the condition's field,
+/// `http_header_name` is set for `http-header` conditions and `query_strings`
+/// for `query-string` conditions.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ConditionSnapshot {
+    pub field: String,
+    pub values: Vec<String>,
+    pub http_header_name: Option<String>,
+    pub query_strings: Vec<QueryStringSnapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QueryStringSnapshot {
+    pub key: Option<String>,
+    pub value: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TargetGroupSnapshot {
+    pub arn: String,
+    pub name: String,
+    pub protocol: Option<String>,
+    pub protocol_version: Option<String>,
+    pub port: Option<i32>,
+    pub vpc_id: Option<String>,
+    pub target_type: String,
+    pub ip_address_type: Option<String>,
+    pub health_check: HealthCheckSnapshot,
+    pub attributes: BTreeMap<String, String>,
+    pub targets: Vec<TargetSnapshot>,
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HealthCheckSnapshot {
+    pub enabled: Option<bool>,
+    pub protocol: Option<String>,
+    pub port: Option<String>,
+    pub path: Option<String>,
+    pub interval_seconds: Option<i32>,
+    pub timeout_seconds: Option<i32>,
+    pub healthy_threshold_count: Option<i32>,
+    pub unhealthy_threshold_count: Option<i32>,
+    pub matcher_http_code: Option<String>,
+    pub matcher_grpc_code: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TargetSnapshot {
+    pub id: String,
+    pub port: Option<i32>,
+    pub availability_zone: Option<String>,
+}
+
+/// Writes the snapshot to
+/// `<archive_dir>/<account>/<region>/<name>_<family>_<time>.json` and returns
+/// its path. Fails rather than overwrite an existing snapshot.
+pub fn write_snapshot(archive_dir: &str, snapshot: &Snapshot) -> Result<String, String> {
+    let dir = Path::new(archive_dir)
+        .join(&snapshot.account_id)
+        .join(&snapshot.region);
+    fs::create_dir_all(&dir).map_err(|e| format!("error creating {}: {}", dir.display(), e))?;
+
+    let file_name = dir.join(format!(
+        "{}_{}_{}.json",
+        snapshot.load_balancer.name(),
+        snapshot.load_balancer.family(),
+        snapshot.taken_at.replace(':', "")
+    ));
+    let contents = serde_json::to_string_pretty(snapshot).map_err(|e| e.to_string())?;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&file_name)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| format!("error writing {}: {}", file_name.display(), e))?;
+
+    Ok(file_name.display().to_string())
+}
+
+pub fn read_snapshot(file_name: &str) -> Result<Snapshot, String> {
+    let contents = fs::read_to_string(file_name).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+fn to_btree(map: HashMap<String, String>) -> BTreeMap<String, String> {
+    map.into_iter().collect()
+}
+
+pub async fn take_elb_snapshot(
+    client: &ELBClient,
+    account_id: &str,
+    region: &str,
+    name: &str,
+) -> Result<Snapshot, String> {
+    let resp = retry::send("ELB DescribeLoadBalancers", || {
+        client
+            .describe_load_balancers()
+            .load_balancer_names(name)
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error describing load balancer: {}", e))?;
+    let lb = resp
+        .load_balancer_descriptions()
+        .unwrap_or_default()
+        .first()
+        .cloned()
+        .ok_or_else(|| format!("load balancer {} not found", name))?;
+
+    let resp = retry::send("ELB DescribeLoadBalancerAttributes", || {
+        client
+            .describe_load_balancer_attributes()
+            .load_balancer_name(name)
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error describing attributes: {}", e))?;
+    let mut attributes = ClassicAttributesSnapshot::default();
+    if let Some(lb_attributes) = resp.load_balancer_attributes() {
+        if let Some(cross_zone) = lb_attributes.cross_zone_load_balancing() {
+            attributes.cross_zone_load_balancing = cross_zone.enabled();
+        }
+        if let Some(draining) = lb_attributes.connection_draining() {
+            attributes.connection_draining_enabled = draining.enabled();
+            attributes.connection_draining_timeout = draining.timeout();
+        }
+        if let Some(settings) = lb_attributes.connection_settings() {
+            attributes.idle_timeout = settings.idle_timeout();
+        }
+        attributes.access_log = lb_attributes.access_log().map(|log| AccessLogSnapshot {
+            enabled: log.enabled(),
+            s3_bucket_name: log.s3_bucket_name().map(|s| s.to_string()),
+            emit_interval: log.emit_interval(),
+            s3_bucket_prefix: log.s3_bucket_prefix().map(|s| s.to_string()),
+        });
+    }
+
+    let resp = retry::send("ELB DescribeLoadBalancerPolicies", || {
+        client
+            .describe_load_balancer_policies()
+            .load_balancer_name(name)
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error describing policies: {}", e))?;
+    let policies = resp
+        .policy_descriptions()
+        .unwrap_or_default()
+        .iter()
+        .map(|policy| ClassicPolicySnapshot {
+            name: policy.policy_name().unwrap_or_default().to_string(),
+            type_name: policy.policy_type_name().unwrap_or_default().to_string(),
+            attributes: policy
+                .policy_attribute_descriptions()
+                .unwrap_or_default()
+                .iter()
+                .map(|attribute| AttributeSnapshot {
+                    name: attribute.attribute_name().unwrap_or_default().to_string(),
+                    value: attribute.attribute_value().unwrap_or_default().to_string(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let mut tags = get_elb_tags(client, &[name.to_string()]).await?;
+
+    let listeners = lb
+        .listener_descriptions()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|description| {
+            description
+                .listener()
+                .map(|listener| ClassicListenerSnapshot {
+                    protocol: listener.protocol().unwrap_or_default().to_string(),
+                    load_balancer_port: listener.load_balancer_port(),
+                    instance_protocol: listener.instance_protocol().map(|s| s.to_string()),
+                    instance_port: listener.instance_port(),
+                    ssl_certificate_id: listener.ssl_certificate_id().map(|s| s.to_string()),
+                    policy_names: description.policy_names().unwrap_or_default().to_vec(),
+                })
+        })
+        .collect();
+    let backend_servers = lb
+        .backend_server_descriptions()
+        .unwrap_or_default()
+        .iter()
+        .map(|backend| ClassicBackendServerSnapshot {
+            instance_port: backend.instance_port(),
+            policy_names: backend.policy_names().unwrap_or_default().to_vec(),
+        })
+        .collect();
+
+    Ok(Snapshot {
+        taken_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        account_id: account_id.to_string(),
+        region: region.to_string(),
+        load_balancer: LoadBalancerSnapshot::Classic(ClassicSnapshot {
+            name: name.to_string(),
+            dns_name: lb.dns_name().unwrap_or_default().to_string(),
+            scheme: lb.scheme().unwrap_or_default().to_string(),
+            vpc_id: lb.vpc_id().map(|s| s.to_string()),
+            availability_zones: lb.availability_zones().unwrap_or_default().to_vec(),
+            subnets: lb.subnets().unwrap_or_default().to_vec(),
+            security_groups: lb.security_groups().unwrap_or_default().to_vec(),
+            listeners,
+            backend_servers,
+            policies,
+            health_check: lb.health_check().map(|check| ClassicHealthCheckSnapshot {
+                target: check.target().unwrap_or_default().to_string(),
+                interval: check.interval(),
+                timeout: check.timeout(),
+                unhealthy_threshold: check.unhealthy_threshold(),
+                healthy_threshold: check.healthy_threshold(),
+            }),
+            instances: lb
+                .instances()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|instance| instance.instance_id())
+                .map(|id| id.to_string())
+                .collect(),
+            attributes,
+            tags: to_btree(tags.remove(name).unwrap_or_default()),
+        }),
+    })
+}
+
+pub async fn take_elbv2_snapshot(
+    client: &ELBv2Client,
+    account_id: &str,
+    region: &str,
+    arn: &str,
+) -> Result<Snapshot, String> {
+    let resp = retry::send("ELBv2 DescribeLoadBalancers", || {
+        client
+            .describe_load_balancers()
+            .load_balancer_arns(arn)
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error describing load balancer: {}", e))?;
+    let lb = resp
+        .load_balancers()
+        .unwrap_or_default()
+        .first()
+        .cloned()
+        .ok_or_else(|| format!("load balancer {} not found", arn))?;
+
+    let attributes = get_lb_attributes(client, arn).await?;
+
+    let mut listeners: Vec<ListenerSnapshot> = vec![];
+    for listener in get_listeners(client, arn).await? {
+        listeners.push(listener_snapshot(client, &listener).await?);
+    }
+
+    let mut target_groups: Vec<TargetGroupSnapshot> = vec![];
+    for tg in get_target_groups(client, arn).await? {
+        target_groups.push(target_group_snapshot(client, &tg).await?);
+    }
+    let tg_arns = target_groups
+        .iter()
+        .map(|tg| tg.arn.clone())
+        .collect::<Vec<String>>();
+    let mut tg_tags = get_elbv2_tags(client, &tg_arns).await?;
+    for tg in target_groups.iter_mut() {
+        tg.tags = to_btree(tg_tags.remove(&tg.arn).unwrap_or_default());
+    }
+
+    let mut tags = get_elbv2_tags(client, &[arn.to_string()]).await?;
+
+    let subnets = lb
+        .availability_zones()
+        .unwrap_or_default()
+        .iter()
+        .map(|az| {
+            let address = az.load_balancer_addresses().unwrap_or_default().first();
+            SubnetMappingSnapshot {
+                subnet_id: az.subnet_id().unwrap_or_default().to_string(),
+                allocation_id: address
+                    .and_then(|a| a.allocation_id())
+                    .map(|s| s.to_string()),
+                private_ipv4_address: address
+                    .and_then(|a| a.private_i_pv4_address())
+                    .map(|s| s.to_string()),
+                ipv6_address: address
+                    .and_then(|a| a.i_pv6_address())
+                    .map(|s| s.to_string()),
+            }
+        })
+        .collect();
+
+    Ok(Snapshot {
+        taken_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        account_id: account_id.to_string(),
+        region: region.to_string(),
+        load_balancer: LoadBalancerSnapshot::Elbv2(Elbv2Snapshot {
+            arn: arn.to_string(),
+            name: lb.load_balancer_name().unwrap_or_default().to_string(),
+            dns_name: lb.dns_name().unwrap_or_default().to_string(),
+            lb_type: lb
+                .r#type()
+                .map(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            scheme: lb
+                .scheme()
+                .map(|s| s.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            ip_address_type: lb
+                .ip_address_type()
+                .map(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            vpc_id: lb.vpc_id().unwrap_or_default().to_string(),
+            subnets,
+            security_groups: lb.security_groups().unwrap_or_default().to_vec(),
+            attributes,
+            tags: to_btree(tags.remove(arn).unwrap_or_default()),
+            listeners,
+            target_groups,
+        }),
+    })
+}
+
+async fn get_lb_attributes(
+    client: &ELBv2Client,
+    arn: &str,
+) -> Result<BTreeMap<String, String>, String> {
+    let resp = retry::send("ELBv2 DescribeLoadBalancerAttributes", || {
+        client
+            .describe_load_balancer_attributes()
+            .load_balancer_arn(arn)
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error describing attributes: {}", e))?;
+
+    Ok(resp
+        .attributes()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|attribute| {
+            attribute.key().map(|key| {
+                (
+                    key.to_string(),
+                    attribute.value().unwrap_or_default().to_string(),
+                )
+            })
+        })
+        .collect())
+}
+
+async fn get_listeners(client: &ELBv2Client, arn: &str) -> Result<Vec<Listener>, String> {
+    let mut listeners: Vec<Listener> = vec![];
+    let mut next_marker = None;
+
+    loop {
+        let resp = retry::send("ELBv2 DescribeListeners", || {
+            client
+                .describe_listeners()
+                .load_balancer_arn(arn)
+                .set_marker(next_marker.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error describing listeners: {}", e))?;
+
+        listeners.extend_from_slice(resp.listeners().unwrap_or_default());
+        next_marker = resp.next_marker().map(|marker| marker.to_string());
+        if next_marker.is_none() {
+            break;
+        }
+    }
+
+    Ok(listeners)
+}
+
+async fn listener_snapshot(
+    client: &ELBv2Client,
+    listener: &Listener,
+) -> Result<ListenerSnapshot, String> {
+    let listener_arn = listener.listener_arn().unwrap_or_default();
+
+    // Only the default certificate is part of the listener description, the
+    // others have to be listed separately.
+    let mut certificates: Vec<CertificateSnapshot> = vec![];
+    if !listener.certificates().unwrap_or_default().is_empty() {
+        let mut next_marker = None;
+        loop {
+            let resp = retry::send("ELBv2 DescribeListenerCertificates", || {
+                client
+                    .describe_listener_certificates()
+                    .listener_arn(listener_arn)
+                    .set_marker(next_marker.clone())
+                    .send()
+            })
+            .await
+            .map_err(|e| format!("error describing certificates: {}", e))?;
+
+            for certificate in resp.certificates().unwrap_or_default() {
+                certificates.push(CertificateSnapshot {
+                    arn: certificate
+                        .certificate_arn()
+                        .unwrap_or_default()
+                        .to_string(),
+                    is_default: certificate.is_default().unwrap_or_default(),
+                });
+            }
+            next_marker = resp.next_marker().map(|marker| marker.to_string());
+            if next_marker.is_none() {
+                break;
+            }
+        }
+    }
+
+    let mut rules: Vec<RuleSnapshot> = vec![];
+    let mut next_marker = None;
+    loop {
+        let resp = retry::send("ELBv2 DescribeRules", || {
+            client
+                .describe_rules()
+                .listener_arn(listener_arn)
+                .set_marker(next_marker.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error describing rules: {}", e))?;
+
+        for rule in resp.rules().unwrap_or_default() {
+            if rule.is_default() {
+                continue;
+            }
+            rules.push(RuleSnapshot {
+                arn: rule.rule_arn().unwrap_or_default().to_string(),
+                priority: rule.priority().unwrap_or_default().to_string(),
+                conditions: rule
+                    .conditions()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(condition_snapshot)
+                    .collect(),
+                actions: rule
+                    .actions()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(action_snapshot)
+                    .collect(),
+            });
+        }
+        next_marker = resp.next_marker().map(|marker| marker.to_string());
+        if next_marker.is_none() {
+            break;
+        }
+    }
+
+    Ok(ListenerSnapshot {
+        arn: listener_arn.to_string(),
+        port: listener.port(),
+        protocol: listener.protocol().map(|p| p.as_str().to_string()),
+        ssl_policy: listener.ssl_policy().map(|s| s.to_string()),
+        certificates,
+        alpn_policy: listener.alpn_policy().unwrap_or_default().to_vec(),
+        default_actions: listener
+            .default_actions()
+            .unwrap_or_default()
+            .iter()
+            .map(action_snapshot)
+            .collect(),
+        rules,
+    })
+}
+
+fn action_snapshot(action: &Action) -> ActionSnapshot {
+    ActionSnapshot {
+        action_type: action
+            .r#type()
+            .map(|t| t.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        order: action.order(),
+        target_group_arn: action.target_group_arn().map(|s| s.to_string()),
+        forward_target_groups: action
+            .forward_config()
+            .and_then(|forward| forward.target_groups())
+            .unwrap_or_default()
+            .iter()
+            .map(|tg| WeightedTargetGroupSnapshot {
+                target_group_arn: tg.target_group_arn().unwrap_or_default().to_string(),
+                weight: tg.weight(),
+            })
+            .collect(),
+        forward_stickiness: action
+            .forward_config()
+            .and_then(|forward| forward.target_group_stickiness_config())
+            .map(|stickiness| StickinessSnapshot {
+                enabled: stickiness.enabled(),
+                duration_seconds: stickiness.duration_seconds(),
+            }),
+        redirect: action.redirect_config().map(|redirect| RedirectSnapshot {
+            protocol: redirect.protocol().map(|s| s.to_string()),
+            port: redirect.port().map(|s| s.to_string()),
+            host: redirect.host().map(|s| s.to_string()),
+            path: redirect.path().map(|s| s.to_string()),
+            query: redirect.query().map(|s| s.to_string()),
+            status_code: redirect
+                .status_code()
+                .map(|code| code.as_str())
+                .unwrap_or_default()
+                .to_string(),
+        }),
+        fixed_response: action
+            .fixed_response_config()
+            .map(|response| FixedResponseSnapshot {
+                status_code: response.status_code().unwrap_or_default().to_string(),
+                content_type: response.content_type().map(|s| s.to_string()),
+                message_body: response.message_body().map(|s| s.to_string()),
+            }),
+        authenticate_oidc: action
+            .authenticate_oidc_config()
+            .map(|oidc| AuthenticateOidcSnapshot {
+                issuer: oidc.issuer().map(|s| s.to_string()),
+                authorization_endpoint: oidc.authorization_endpoint().map(|s| s.to_string()),
+                token_endpoint: oidc.token_endpoint().map(|s| s.to_string()),
+                user_info_endpoint: oidc.user_info_endpoint().map(|s| s.to_string()),
+                client_id: oidc.client_id().map(|s| s.to_string()),
+                client_secret_required: true,
+                session_cookie_name: oidc.session_cookie_name().map(|s| s.to_string()),
+                scope: oidc.scope().map(|s| s.to_string()),
+                session_timeout: oidc.session_timeout(),
+                authentication_request_extra_params: to_btree(
+                    oidc.authentication_request_extra_params()
+                        .cloned()
+                        .unwrap_or_default(),
+                ),
+                on_unauthenticated_request: oidc
+                    .on_unauthenticated_request()
+                    .map(|behavior| behavior.as_str().to_string()),
+            }),
+        authenticate_cognito: action.authenticate_cognito_config().map(|cognito| {
+            AuthenticateCognitoSnapshot {
+                user_pool_arn: cognito.user_pool_arn().map(|s| s.to_string()),
+                user_pool_client_id: cognito.user_pool_client_id().map(|s| s.to_string()),
+                user_pool_domain: cognito.user_pool_domain().map(|s| s.to_string()),
+                session_cookie_name: cognito.session_cookie_name().map(|s| s.to_string()),
+                scope: cognito.scope().map(|s| s.to_string()),
+                session_timeout: cognito.session_timeout(),
+                authentication_request_extra_params: to_btree(
+                    cognito
+                        .authentication_request_extra_params()
+                        .cloned()
+                        .unwrap_or_default(),
+                ),
+                on_unauthenticated_request: cognito
+                    .on_unauthenticated_request()
+                    .map(|behavior| behavior.as_str().to_string()),
+            }
+        }),
+    }
+}
+
+fn condition_snapshot(condition: &RuleCondition) -> ConditionSnapshot {
+    let field = condition.field().unwrap_or_default().to_string();
+    let typed_values = match field.as_str() {
+        "host-header" => condition.host_header_config().and_then(|c| c.values()),
+        "path-pattern" => condition.path_pattern_config().and_then(|c| c.values()),
+        "http-header" => condition.http_header_config().and_then(|c| c.values()),
+        "http-request-method" => condition
+            .http_request_method_config()
+            .and_then(|c| c.values()),
+        "source-ip" => condition.source_ip_config().and_then(|c| c.values()),
+        _ => None,
+    };
+
+    ConditionSnapshot {
+        values: typed_values
+            .or_else(|| condition.values())
+            .unwrap_or_default()
+            .to_vec(),
+        http_header_name: condition
+            .http_header_config()
+            .and_then(|c| c.http_header_name())
+            .map(|s| s.to_string()),
+        query_strings: condition
+            .query_string_config()
+            .and_then(|c| c.values())
+            .unwrap_or_default()
+            .iter()
+            .map(|pair| QueryStringSnapshot {
+                key: pair.key().map(|s| s.to_string()),
+                value: pair.value().unwrap_or_default().to_string(),
+            })
+            .collect(),
+        field,
+    }
+}
+
+async fn get_target_groups(client: &ELBv2Client, arn: &str) -> Result<Vec<TargetGroupV2>, String> {
+    let mut tgs: Vec<TargetGroupV2> = vec![];
+    let mut next_marker = None;
+
+    loop {
+        let resp = retry::send("ELBv2 DescribeTargetGroups", || {
+            client
+                .describe_target_groups()
+                .load_balancer_arn(arn)
+                .set_marker(next_marker.clone())
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error describing target groups: {}", e))?;
+
+        tgs.extend_from_slice(resp.target_groups().unwrap_or_default());
+        next_marker = resp.next_marker().map(|marker| marker.to_string());
+        if next_marker.is_none() {
+            break;
+        }
+    }
+
+    Ok(tgs)
+}
+
+async fn target_group_snapshot(
+    client: &ELBv2Client,
+    tg: &TargetGroupV2,
+) -> Result<TargetGroupSnapshot, String> {
+    let tg_arn = tg.target_group_arn().unwrap_or_default();
+
+    let resp = retry::send("ELBv2 DescribeTargetGroupAttributes", || {
+        client
+            .describe_target_group_attributes()
+            .target_group_arn(tg_arn)
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error describing target group attributes: {}", e))?;
+    let attributes = resp
+        .attributes()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|attribute| {
+            attribute.key().map(|key| {
+                (
+                    key.to_string(),
+                    attribute.value().unwrap_or_default().to_string(),
+                )
+            })
+        })
+        .collect();
+
+    let resp = retry::send("ELBv2 DescribeTargetHealth", || {
+        client
+            .describe_target_health()
+            .target_group_arn(tg_arn)
+            .send()
+    })
+    .await
+    .map_err(|e| format!("error describing targets: {}", e))?;
+    let targets = resp
+        .target_health_descriptions()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|description| description.target())
+        .map(|target| TargetSnapshot {
+            id: target.id().unwrap_or_default().to_string(),
+            port: target.port(),
+            availability_zone: target.availability_zone().map(|s| s.to_string()),
+        })
+        .collect();
+
+    Ok(TargetGroupSnapshot {
+        arn: tg_arn.to_string(),
+        name: tg.target_group_name().unwrap_or_default().to_string(),
+        protocol: tg.protocol().map(|p| p.as_str().to_string()),
+        protocol_version: tg.protocol_version().map(|s| s.to_string()),
+        port: tg.port(),
+        vpc_id: tg.vpc_id().map(|s| s.to_string()),
+        target_type: tg
+            .target_type()
+            .map(|t| t.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        ip_address_type: tg.ip_address_type().map(|t| t.as_str().to_string()),
+        health_check: HealthCheckSnapshot {
+            enabled: tg.health_check_enabled(),
+            protocol: tg.health_check_protocol().map(|p| p.as_str().to_string()),
+            port: tg.health_check_port().map(|s| s.to_string()),
+            path: tg.health_check_path().map(|s| s.to_string()),
+            interval_seconds: tg.health_check_interval_seconds(),
+            timeout_seconds: tg.health_check_timeout_seconds(),
+            healthy_threshold_count: tg.healthy_threshold_count(),
+            unhealthy_threshold_count: tg.unhealthy_threshold_count(),
+            matcher_http_code: tg
+                .matcher()
+                .and_then(|m| m.http_code())
+                .map(|s| s.to_string()),
+            matcher_grpc_code: tg
+                .matcher()
+                .and_then(|m| m.grpc_code())
+                .map(|s| s.to_string()),
+        },
+        attributes,
+        targets,
+        tags: BTreeMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classic(name: &str) -> LoadBalancerSnapshot {
+        LoadBalancerSnapshot::Classic(ClassicSnapshot {
+            name: name.to_string(),
+            dns_name: String::new(),
+            scheme: "internal".to_string(),
+            vpc_id: None,
+            availability_zones: vec![],
+            subnets: vec![],
+            security_groups: vec![],
+            listeners: vec![],
+            backend_servers: vec![],
+            policies: vec![],
+            health_check: None,
+            instances: vec![],
+            attributes: ClassicAttributesSnapshot::default(),
+            tags: BTreeMap::new(),
+        })
+    }
+
+    fn elbv2(name: &str) -> LoadBalancerSnapshot {
+        LoadBalancerSnapshot::Elbv2(Elbv2Snapshot {
+            arn: format!(
+                "arn:aws:elasticloadbalancing:eu-west-1:123456789012:loadbalancer/app/{}/1",
+                name
+            ),
+            name: name.to_string(),
+            dns_name: String::new(),
+            lb_type: "application".to_string(),
+            scheme: "internal".to_string(),
+            ip_address_type: "ipv4".to_string(),
+            vpc_id: "vpc-0123456789abcdef0".to_string(),
+            subnets: vec![],
+            security_groups: vec![],
+            attributes: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            listeners: vec![],
+            target_groups: vec![],
+        })
+    }
+
+    fn snapshot(load_balancer: LoadBalancerSnapshot) -> Snapshot {
+        Snapshot {
+            taken_at: "2026-01-01T00:00:00Z".to_string(),
+            account_id: "123456789012".to_string(),
+            region: "eu-west-1".to_string(),
+            load_balancer,
+        }
+    }
+
+    #[test]
+    fn never_overwrites_a_snapshot() {
+        let archive_dir =
+            std::env::temp_dir().join(format!("lb-janitor-snapshots-{}", std::process::id()));
+        let archive_dir = archive_dir.to_str().unwrap();
+
+        let classic_file = write_snapshot(archive_dir, &snapshot(classic("web"))).unwrap();
+        let elbv2_file = write_snapshot(archive_dir, &snapshot(elbv2("web"))).unwrap();
+        assert!(classic_file.ends_with("web_classic_2026-01-01T000000Z.json"));
+        assert!(elbv2_file.ends_with("web_elbv2_2026-01-01T000000Z.json"));
+
+        let again = write_snapshot(archive_dir, &snapshot(elbv2("web")));
+        assert!(again
+            .unwrap_err()
+            .starts_with(&format!("error writing {}", elbv2_file)));
+        assert_eq!(
+            read_snapshot(&classic_file).unwrap().load_balancer.family(),
+            "classic"
+        );
+
+        fs::remove_dir_all(archive_dir).unwrap();
+    }
+}
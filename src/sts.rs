@@ -0,0 +1,171 @@
+use crate::retry;
+
+use aws_config::meta::region::RegionProviderChain;
+use aws_credential_types::cache::{
+    CredentialsCache, ProvideCachedCredentials, SharedCredentialsCache,
+};
+use aws_credential_types::provider::{
+    error::CredentialsError, future, ProvideCredentials, SharedCredentialsProvider,
+};
+use aws_credential_types::Credentials;
+use aws_sdk_sts::types::DateTime as StsDateTime;
+use aws_sdk_sts::Client as StsClient;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+
+/// Credentials refreshed shortly before they expire are assumed again.
+const REFRESH_BEFORE_EXPIRY: Duration = Duration::from_secs(300);
+/// How long assuming the role may take, retries included.
+const ASSUME_ROLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Assumes a role with the caller's default credentials. AssumeRole goes
+/// through `retry::send` like every other call, so it follows the `[retry]`
+/// policy and shows up in the retry counts.
+#[derive(Debug)]
+struct AssumeRole {
+    client: StsClient,
+    iam_role: String,
+    session_duration_seconds: u64,
+}
+
+impl AssumeRole {
+    async fn credentials(&self) -> Result<Credentials, String> {
+        let iam_role = self.iam_role.as_str();
+        let assumed_role = retry::send("STS AssumeRole", || {
+            self.client
+                .assume_role()
+                .role_arn(iam_role)
+                .role_session_name("lb_janitor_assumerole_session")
+                .duration_seconds(self.session_duration_seconds as i32)
+                .send()
+        })
+        .await
+        .map_err(|e| format!("error assuming role {}: {}", iam_role, e))?;
+
+        let credentials = assumed_role
+            .credentials()
+            .ok_or_else(|| format!("assuming role {} returned no credentials", iam_role))?;
+        let missing = |field: &str| format!("credentials of role {} have no {}", iam_role, field);
+        let access_key_id = credentials
+            .access_key_id()
+            .ok_or_else(|| missing("access key id"))?;
+        let secret_access_key = credentials
+            .secret_access_key()
+            .ok_or_else(|| missing("secret access key"))?;
+        let session_token = credentials
+            .session_token()
+            .ok_or_else(|| missing("session token"))?;
+        let expiry: StsDateTime = *credentials
+            .expiration()
+            .ok_or_else(|| missing("expiration"))?;
+        let expiry: SystemTime = SystemTime::try_from(expiry)
+            .map_err(|e| format!("invalid expiration of role {}: {}", iam_role, e))?;
+
+        Ok(Credentials::new(
+            access_key_id,
+            secret_access_key,
+            Some(session_token.to_string()),
+            Some(expiry),
+            "AssumeRole",
+        ))
+    }
+}
+
+impl ProvideCredentials for AssumeRole {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        future::ProvideCredentials::new(async move {
+            self.credentials()
+                .await
+                .map_err(CredentialsError::provider_error)
+        })
+    }
+}
+
+/// Credentials of an assumed role, cached once for every client that uses
+/// them, so the role is assumed again only when they are about to expire.
+#[derive(Debug)]
+struct CachedRoleCredentials(SharedCredentialsCache);
+
+impl ProvideCredentials for CachedRoleCredentials {
+    fn provide_credentials<'a>(&'a self) -> future::ProvideCredentials<'a>
+    where
+        Self: 'a,
+    {
+        self.0.provide_cached_credentials()
+    }
+}
+
+/// Returns a provider of the credentials of `iam_role`, assumed with the
+/// caller's default credentials for `session_duration_seconds`. The provider
+/// assumes the role again before the credentials expire, so one provider can
+/// be shared by every client of the account for the whole run. The role is
+/// assumed once up front so a role that can't be assumed fails here.
+pub async fn assume_role(
+    iam_role: &str,
+    session_duration_seconds: u64,
+) -> Result<SharedCredentialsProvider, String> {
+    let region_provider = RegionProviderChain::default_provider().or_else("ap-southeast-1");
+
+    let config = aws_config::from_env()
+        .retry_config(retry::sdk_retry_config())
+        .region(region_provider)
+        .load()
+        .await;
+    let assume_role = AssumeRole {
+        client: StsClient::new(&config),
+        iam_role: iam_role.to_string(),
+        session_duration_seconds,
+    };
+    let cache = CredentialsCache::lazy_builder()
+        .buffer_time(REFRESH_BEFORE_EXPIRY)
+        .load_timeout(ASSUME_ROLE_TIMEOUT)
+        .into_credentials_cache()
+        .create_cache(SharedCredentialsProvider::new(assume_role));
+    let credentials = SharedCredentialsProvider::new(CachedRoleCredentials(cache));
+
+    // The cause of a provider error is the message of `AssumeRole`.
+    if let Err(e) = credentials.provide_credentials().await {
+        return Err(e
+            .source()
+            .map(|source| source.to_string())
+            .unwrap_or_else(|| e.to_string()));
+    }
+
+    Ok(credentials)
+}
+
+/// Confirms with GetCallerIdentity that `credentials` belong to `account_id`.
+/// Must pass before any destructive call is made with them.
+pub async fn verify_account(
+    credentials: &SharedCredentialsProvider,
+    account_id: &str,
+) -> Result<(), String> {
+    let region_provider = RegionProviderChain::default_provider().or_else("ap-southeast-1");
+
+    let config = aws_config::from_env()
+        .retry_config(retry::sdk_retry_config())
+        .credentials_provider(credentials.clone())
+        .region(region_provider)
+        .load()
+        .await;
+    let sts_client: StsClient = StsClient::new(&config);
+
+    let identity = retry::send("STS GetCallerIdentity", || {
+        sts_client.get_caller_identity().send()
+    })
+    .await
+    .map_err(|e| format!("error getting caller identity: {}", e))?;
+
+    match identity.account() {
+        Some(account) if account == account_id => Ok(()),
+        Some(account) => Err(format!(
+            "credentials belong to account {}, expected {}",
+            account, account_id
+        )),
+        None => Err("caller identity has no account".to_string()),
+    }
+}
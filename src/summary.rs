@@ -0,0 +1,119 @@
+use crate::error::Error;
+use crate::retry::RetryCount;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What a run did in one account.
+pub struct AccountSummary {
+    pub account_id: String,
+    pub deleted: Vec<String>,
+    pub errors: Vec<Error>,
+}
+
+impl fmt::Debug for AccountSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccountSummary")
+            .field("account_id", &self.account_id)
+            .field("deleted", &self.deleted)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+/// Deletions and non-fatal errors of a run, per account, printed at the end
+/// of the run and turned into the exit code, with the retries of every AWS
+/// operation.
+#[derive(Default)]
+pub struct RunSummary {
+    pub accounts: Vec<AccountSummary>,
+    pub retries: BTreeMap<String, RetryCount>,
+}
+
+impl fmt::Debug for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunSummary")
+            .field("accounts", &self.accounts)
+            .field("retries", &self.retries)
+            .finish()
+    }
+}
+
+impl RunSummary {
+    pub fn account(&mut self, account_id: &str) -> &mut AccountSummary {
+        let index = match self
+            .accounts
+            .iter()
+            .position(|account| account.account_id == account_id)
+        {
+            Some(index) => index,
+            None => {
+                self.accounts.push(AccountSummary {
+                    account_id: account_id.to_string(),
+                    deleted: vec![],
+                    errors: vec![],
+                });
+                self.accounts.len() - 1
+            }
+        };
+        &mut self.accounts[index]
+    }
+
+    /// Records the outcome of every deletion attempted in an account.
+    pub fn add_deletions(&mut self, account_id: &str, deletions: Vec<Result<String, Error>>) {
+        let account = self.account(account_id);
+        for deletion in deletions {
+            match deletion {
+                Ok(id) => account.deleted.push(id),
+                Err(e) => account.errors.push(e),
+            }
+        }
+    }
+
+    pub fn add_errors(&mut self, account_id: &str, errors: Vec<Error>) {
+        self.account(account_id).errors.extend(errors);
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.accounts
+            .iter()
+            .map(|account| account.errors.len())
+            .sum()
+    }
+
+    /// 0 when the run went cleanly, 1 when anything failed along the way.
+    pub fn exit_code(&self) -> i32 {
+        if self.error_count() == 0 {
+            0
+        } else {
+            1
+        }
+    }
+
+    pub fn print(&self) {
+        if self.accounts.is_empty() && self.retries.is_empty() {
+            return;
+        }
+        println!("\nRun summary:");
+        for account in self.accounts.iter() {
+            println!(
+                "Account {}: {} deleted, {} errors",
+                account.account_id,
+                account.deleted.len(),
+                account.errors.len()
+            );
+            for e in account.errors.iter() {
+                println!("  Error: {}", e);
+            }
+        }
+        for (operation, count) in self.retries.iter() {
+            println!(
+                "{}: {} retries, {} calls failed after every attempt",
+                operation, count.retries, count.exhausted
+            );
+        }
+        if self.error_count() > 0 {
+            println!("Finished with {} errors", self.error_count());
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use aws_smithy_types_convert::date_time::DateTimeExt;
 use aws_types::region::Region;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs::write;
 use std::io::Error;
@@ -13,21 +16,20 @@ pub fn parse_regions_arg(regions: &[String]) -> Vec<Region> {
     regions_obj
 }
 
-pub fn parse_vpc_ids_arg(vpc_ids: &[String]) -> HashMap<String, bool> {
-    let mut vpc_ids_map: HashMap<String, bool> = HashMap::new();
-
-    for vpc_id in vpc_ids {
-        vpc_ids_map.insert(vpc_id.to_string(), true);
-    }
-
-    vpc_ids_map
-}
-
+/// The account id of an IAM role ARN such as
+/// `arn:aws:iam::123456789012:role/lb-janitor`.
 pub fn extract_account_id_from_role_arn(arn: &str) -> Option<String> {
     let parts: Vec<&str> = arn.split(':').collect();
+    if parts.len() != 6 || parts[0] != "arn" || parts[2] != "iam" {
+        return None;
+    }
 
     let account_id = parts[4];
-    Some(account_id.to_string())
+    if account_id.len() == 12 && account_id.chars().all(|c| c.is_ascii_digit()) {
+        Some(account_id.to_string())
+    } else {
+        None
+    }
 }
 
 pub fn extract_id_from_lb_arn(arn: &str) -> Option<String> {
@@ -45,6 +47,8 @@ pub fn extract_namespace_from_lb_type(arn: &str) -> Option<String> {
         Some("AWS/NetworkELB".to_string())
     } else if arn.contains("loadbalancer/app") {
         Some("AWS/ApplicationELB".to_string())
+    } else if arn.contains("loadbalancer/gwy") {
+        Some("AWS/GatewayELB".to_string())
     } else {
         None
     }
@@ -69,12 +73,78 @@ pub fn extract_region_from_elbv2_arn(arn: &str) -> Option<String> {
     }
 }
 
-pub fn extract_region_from_elb_dns(dns_name: &str) -> Option<String> {
-    let parts: Vec<&str> = dns_name.split('.').collect();
-    if parts.len() > 2 {
-        Some(parts[1].to_string())
+pub fn to_chrono_time(time: Option<&aws_smithy_types::DateTime>) -> Option<DateTime<Utc>> {
+    time.and_then(|time| time.to_chrono_utc().ok())
+}
+
+pub fn format_time(time: &Option<DateTime<Utc>>) -> String {
+    match time {
+        Some(time) => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        None => String::new(),
+    }
+}
+
+/// Whether a load balancer created at `created_time` is younger than
+/// `min_age_days`. A missing creation time is never considered too new.
+pub fn is_too_new(created_time: &Option<DateTime<Utc>>, min_age_days: i64) -> bool {
+    match created_time {
+        Some(created_time) => Utc::now() - *created_time < Duration::days(min_age_days),
+        None => false,
+    }
+}
+
+/// Whole days since the load balancer was last active. Without activity in
+/// the lookback window it has been idle at least since the window started, or
+/// since it was created if that is later, which is marked with a trailing `+`.
+pub fn idle_days(
+    last_active_at: &Option<DateTime<Utc>>,
+    created_time: &Option<DateTime<Utc>>,
+    days: i64,
+) -> String {
+    let now = Utc::now();
+    match last_active_at {
+        Some(last_active_at) => (now - *last_active_at).num_days().to_string(),
+        None => {
+            let window_start = now - Duration::days(days);
+            match created_time {
+                Some(created_time) if *created_time > window_start => {
+                    (now - *created_time).num_days().to_string()
+                }
+                _ => format!("{}+", days),
+            }
+        }
+    }
+}
+
+pub fn format_tags(tags: &HashMap<String, String>) -> String {
+    let mut tags = tags
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<String>>();
+    tags.sort();
+    tags.join("; ")
+}
+
+/// Returns the hex encoded SHA-256 of the given parts, used to tell whether the
+/// configuration of a load balancer changed between two runs.
+pub fn config_hash(parts: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
     } else {
-        None
+        value.to_string()
     }
 }
 
@@ -0,0 +1,810 @@
+use crate::cloudwatch::period_for_days;
+use crate::elb::NO_VPC;
+use crate::error::Error;
+use crate::models::{
+    AppConfig, AwsAccount, AwsConfig, LimitsConfig, MetricDimension, MetricRule, NamePattern,
+    RetryPolicy, RunOption, ScoringConfig, TagSelector,
+};
+
+use regex::Regex;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use toml::Spanned;
+
+/// Regions load balancers can be discovered in when this list was last
+/// updated. Other regions are only warned about, as they may be newer.
+pub const KNOWN_REGIONS: &[&str] = &[
+    "af-south-1",
+    "ap-east-1",
+    "ap-east-2",
+    "ap-northeast-1",
+    "ap-northeast-2",
+    "ap-northeast-3",
+    "ap-south-1",
+    "ap-south-2",
+    "ap-southeast-1",
+    "ap-southeast-2",
+    "ap-southeast-3",
+    "ap-southeast-4",
+    "ap-southeast-5",
+    "ap-southeast-6",
+    "ap-southeast-7",
+    "ca-central-1",
+    "ca-west-1",
+    "cn-north-1",
+    "cn-northwest-1",
+    "eu-central-1",
+    "eu-central-2",
+    "eu-north-1",
+    "eu-south-1",
+    "eu-south-2",
+    "eu-west-1",
+    "eu-west-2",
+    "eu-west-3",
+    "il-central-1",
+    "me-central-1",
+    "me-south-1",
+    "mx-central-1",
+    "sa-east-1",
+    "us-east-1",
+    "us-east-2",
+    "us-gov-east-1",
+    "us-gov-west-1",
+    "us-west-1",
+    "us-west-2",
+];
+
+const ROLE_ARN_PATTERN: &str = r"^arn:aws(-cn|-us-gov)?:iam::\d{12}:role/[\w+=,.@/-]+$";
+const VPC_ID_PATTERN: &str = r"^vpc-([0-9a-f]{8}|[0-9a-f]{17})$";
+
+/// A problem in a config file, at a 1-based line and column. Warnings don't
+/// make the config invalid.
+pub struct Problem {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub warning: bool,
+}
+
+impl fmt::Debug for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Problem")
+            .field("line", &self.line)
+            .field("column", &self.column)
+            .field("message", &self.message)
+            .field("warning", &self.warning)
+            .finish()
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = if self.warning { "warning: " } else { "" };
+        write!(
+            f,
+            "{}:{}: {}{}",
+            self.line, self.column, severity, self.message
+        )
+    }
+}
+
+/// The values of a config that are checked beyond their type, with where
+/// they are in the file. Unlike `AppConfig` it ignores unknown keys, so its
+/// values are still checked when the schema is violated elsewhere.
+#[derive(Deserialize)]
+struct LocatedConfig {
+    days: Option<Spanned<i64>>,
+    min_age_days: Option<Spanned<i64>>,
+    mark_days: Option<Spanned<i64>>,
+    #[serde(default)]
+    limits: LocatedLimits,
+    #[serde(default)]
+    retry: LocatedRetry,
+    aws: Option<LocatedAws>,
+}
+
+#[derive(Default, Deserialize)]
+struct LocatedLimits {
+    max_account_share: Option<Spanned<f64>>,
+}
+
+#[derive(Default, Deserialize)]
+struct LocatedRetry {
+    max_attempts: Option<Spanned<i64>>,
+    jitter: Option<Spanned<f64>>,
+}
+
+#[derive(Deserialize)]
+struct LocatedAws {
+    session_duration_seconds: Option<Spanned<i64>>,
+    #[serde(default)]
+    accounts: Vec<Spanned<LocatedAccount>>,
+}
+
+#[derive(Deserialize)]
+struct LocatedAccount {
+    iam_role: Option<Spanned<String>>,
+    #[serde(default)]
+    regions: Vec<Spanned<String>>,
+    #[serde(default, alias = "vpc_ids")]
+    include_vpc_ids: Vec<Spanned<String>>,
+    #[serde(default)]
+    exclude_vpc_ids: Vec<Spanned<String>>,
+}
+
+/// The layout of a TOML value, with the keys of its tables in file order.
+enum Shape {
+    Table(Vec<(String, Shape)>),
+    Array(Vec<Shape>),
+    Scalar,
+}
+
+struct ShapeVisitor;
+
+impl<'de> Visitor<'de> for ShapeVisitor {
+    type Value = Shape;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a TOML value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<Shape, E> {
+        Ok(Shape::Scalar)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<Shape, E> {
+        Ok(Shape::Scalar)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<Shape, E> {
+        Ok(Shape::Scalar)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<Shape, E> {
+        Ok(Shape::Scalar)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<Shape, E> {
+        Ok(Shape::Scalar)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Shape, A::Error> {
+        let mut elements = vec![];
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Shape::Array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Shape, A::Error> {
+        let mut entries = vec![];
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Shape::Table(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for Shape {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ShapeVisitor)
+    }
+}
+
+/// A TOML value with where its keys and scalars are in the file.
+enum Node {
+    Table(Vec<Entry>),
+    Array(Vec<Node>),
+    Scalar(Spanned<toml::Value>),
+}
+
+/// A key of a TOML table and its value. Keys of implicit tables, e.g. `aws`
+/// in `[[aws.accounts]]`, have no location.
+struct Entry {
+    key: String,
+    span: Option<Range<usize>>,
+    value: Node,
+}
+
+impl Node {
+    /// The location of the first located key or scalar in the value.
+    fn first_span(&self) -> Option<Range<usize>> {
+        match self {
+            Node::Table(entries) => entries
+                .iter()
+                .find_map(|entry| entry.span.clone().or_else(|| entry.value.first_span())),
+            Node::Array(elements) => elements.iter().find_map(Node::first_span),
+            Node::Scalar(scalar) => Some(scalar.span()),
+        }
+    }
+}
+
+/// Reads a value of a known shape, locating its keys and scalars with
+/// `toml::Spanned`. The shape names the keys `Spanned` can't read, those of
+/// implicit tables, and tells which values are scalars, as a `Spanned` table
+/// fails when the table is implicit.
+struct NodeSeed<'a>(&'a Shape);
+
+impl<'de> DeserializeSeed<'de> for NodeSeed<'_> {
+    type Value = Node;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Node, D::Error> {
+        match self.0 {
+            Shape::Scalar => Spanned::deserialize(deserializer).map(Node::Scalar),
+            Shape::Table(_) | Shape::Array(_) => deserializer.deserialize_any(self),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for NodeSeed<'_> {
+    type Value = Node;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a TOML table or array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+        let shapes = match self.0 {
+            Shape::Array(shapes) => shapes,
+            _ => return Err(de::Error::custom("unexpected array")),
+        };
+        let mut elements = vec![];
+        for shape in shapes {
+            match seq.next_element_seed(NodeSeed(shape))? {
+                Some(element) => elements.push(element),
+                None => break,
+            }
+        }
+        Ok(Node::Array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+        let shapes = match self.0 {
+            Shape::Table(shapes) => shapes,
+            _ => return Err(de::Error::custom("unexpected table")),
+        };
+        let mut entries = vec![];
+        for (key, shape) in shapes {
+            let span = match map.next_key::<Spanned<String>>() {
+                Ok(Some(key)) => Some(key.span()),
+                Ok(None) => break,
+                Err(_) => None,
+            };
+            let value = map.next_value_seed(NodeSeed(shape))?;
+            entries.push(Entry {
+                key: key.clone(),
+                span,
+                value,
+            });
+        }
+        Ok(Node::Table(entries))
+    }
+}
+
+/// A deserializer that only records the fields of the struct read from it.
+struct FieldsDeserializer<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for FieldsDeserializer<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = fields;
+        Err(de::Error::custom("only the fields are read"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// The keys of a config struct, aliases included, as its `Deserialize` reads
+/// them.
+fn fields<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsDeserializer(&mut fields));
+    fields
+}
+
+/// The keys of the table at `path`, e.g. `aws.accounts`, or `None` when no
+/// table is expected there.
+fn known_keys(path: &str) -> Option<&'static [&'static str]> {
+    match path {
+        "" => Some(fields::<AppConfig>()),
+        "scoring" => Some(fields::<ScoringConfig>()),
+        "scoring.classic" | "scoring.application" | "scoring.network" | "scoring.gateway" => {
+            Some(fields::<MetricRule>())
+        }
+        "limits" => Some(fields::<LimitsConfig>()),
+        "retry" => Some(fields::<RetryPolicy>()),
+        "aws" => Some(fields::<AwsConfig>()),
+        "aws.accounts" => Some(fields::<AwsAccount>()),
+        _ => None,
+    }
+}
+
+fn parse_as<T: DeserializeOwned>(value: &str) -> Result<(), String> {
+    toml::Value::String(value.to_string())
+        .try_into::<T>()
+        .map(|_| ())
+        .map_err(|e| e.message().trim().to_string())
+}
+
+/// Checks a string, or a string in an array, against the type of `key` in
+/// the table at `path`.
+fn check_string(path: &str, key: &str, value: &str) -> Result<(), String> {
+    match (path, key) {
+        ("", "run_option") => parse_as::<RunOption>(value),
+        ("", "include_tags" | "exclude_tags")
+        | ("aws.accounts", "include_vpc_tags" | "exclude_vpc_tags") => {
+            parse_as::<TagSelector>(value)
+        }
+        ("aws.accounts", "include_names" | "exclude_names" | "include_arns" | "exclude_arns") => {
+            parse_as::<NamePattern>(value)
+        }
+        (_, "dimension") if path.starts_with("scoring.") => parse_as::<MetricDimension>(value),
+        _ => Ok(()),
+    }
+}
+
+/// Reports every unknown key and every string that is not a valid value of
+/// its key in the table at `path`.
+fn check_table(contents: &str, path: &str, entries: &[Entry], problems: &mut Vec<Problem>) {
+    let keys = match known_keys(path) {
+        Some(keys) => keys,
+        None => return,
+    };
+
+    for entry in entries {
+        let name = entry.key.as_str();
+        if !keys.contains(&name) {
+            let location = if path.is_empty() {
+                String::new()
+            } else {
+                format!(" in [{}]", path)
+            };
+            let span = entry.span.clone().or_else(|| entry.value.first_span());
+            problems.push(problem(
+                contents,
+                span.unwrap_or(0..0),
+                format!("unknown key `{}`{}", name, location),
+            ));
+            continue;
+        }
+
+        let nested = if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", path, name)
+        };
+        let elements = match &entry.value {
+            Node::Array(elements) => elements.iter().collect(),
+            node => vec![node],
+        };
+        for element in elements {
+            match element {
+                Node::Table(entries) => check_table(contents, &nested, entries, problems),
+                Node::Scalar(scalar) => {
+                    if let toml::Value::String(string) = scalar.get_ref() {
+                        if let Err(e) = check_string(path, name, string) {
+                            problems.push(problem(contents, scalar.span(), e));
+                        }
+                    }
+                }
+                Node::Array(_) => (),
+            }
+        }
+    }
+}
+
+/// Turns a byte offset into a 1-based line and column.
+fn location(contents: &str, offset: usize) -> (usize, usize) {
+    let before = contents.get(..offset).unwrap_or(contents);
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+fn problem(contents: &str, span: Range<usize>, message: String) -> Problem {
+    let (line, column) = location(contents, span.start);
+    Problem {
+        line,
+        column,
+        message,
+        warning: false,
+    }
+}
+
+fn check_values(contents: &str, conf: &LocatedConfig) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = vec![];
+    let role_arn = Regex::new(ROLE_ARN_PATTERN).unwrap();
+    let vpc_id = Regex::new(VPC_ID_PATTERN).unwrap();
+
+    if let Some(days) = &conf.days {
+        if let Err(e) = period_for_days(*days.get_ref()) {
+            problems.push(problem(
+                contents,
+                days.span(),
+                format!("invalid days: {}", e),
+            ));
+        }
+    }
+    if let Some(min_age_days) = &conf.min_age_days {
+        if *min_age_days.get_ref() < 0 {
+            problems.push(problem(
+                contents,
+                min_age_days.span(),
+                format!(
+                    "min_age_days must not be negative, got {}",
+                    min_age_days.get_ref()
+                ),
+            ));
+        }
+    }
+    if let Some(mark_days) = &conf.mark_days {
+        if *mark_days.get_ref() < 1 {
+            problems.push(problem(
+                contents,
+                mark_days.span(),
+                format!("mark_days must be at least 1, got {}", mark_days.get_ref()),
+            ));
+        }
+    }
+    if let Some(share) = &conf.limits.max_account_share {
+        if !(0.0..=1.0).contains(share.get_ref()) {
+            problems.push(problem(
+                contents,
+                share.span(),
+                format!(
+                    "max_account_share must be between 0 and 1, got {}",
+                    share.get_ref()
+                ),
+            ));
+        }
+    }
+    if let Some(max_attempts) = &conf.retry.max_attempts {
+        if *max_attempts.get_ref() < 1 {
+            problems.push(problem(
+                contents,
+                max_attempts.span(),
+                format!(
+                    "max_attempts must be at least 1, got {}",
+                    max_attempts.get_ref()
+                ),
+            ));
+        }
+    }
+    if let Some(jitter) = &conf.retry.jitter {
+        if !(0.0..=1.0).contains(jitter.get_ref()) {
+            problems.push(problem(
+                contents,
+                jitter.span(),
+                format!("jitter must be between 0 and 1, got {}", jitter.get_ref()),
+            ));
+        }
+    }
+
+    let session_duration_seconds = conf
+        .aws
+        .as_ref()
+        .and_then(|aws| aws.session_duration_seconds.as_ref());
+    if let Some(duration) = session_duration_seconds {
+        if !(900..=43200).contains(duration.get_ref()) {
+            problems.push(problem(
+                contents,
+                duration.span(),
+                format!(
+                    "session_duration_seconds must be between 900 and 43200, got {}",
+                    duration.get_ref()
+                ),
+            ));
+        }
+    }
+
+    let accounts = conf
+        .aws
+        .as_ref()
+        .map(|aws| aws.accounts.as_slice())
+        .unwrap_or_default();
+    for account in accounts {
+        let account_span = account.span();
+        let account = account.get_ref();
+
+        if let Some(iam_role) = &account.iam_role {
+            if !role_arn.is_match(iam_role.get_ref()) {
+                problems.push(problem(
+                    contents,
+                    iam_role.span(),
+                    format!(
+                        "iam_role {:?} is not an IAM role ARN like arn:aws:iam::123456789012:role/lb-janitor",
+                        iam_role.get_ref()
+                    ),
+                ));
+            }
+        }
+        if account.regions.is_empty() {
+            problems.push(problem(
+                contents,
+                account_span,
+                "account has no regions".to_string(),
+            ));
+        }
+        for region in account.regions.iter() {
+            if !KNOWN_REGIONS.contains(&region.get_ref().as_str()) {
+                problems.push(Problem {
+                    warning: true,
+                    ..problem(
+                        contents,
+                        region.span(),
+                        format!("unknown region {:?}", region.get_ref()),
+                    )
+                });
+            }
+        }
+        for id in account
+            .include_vpc_ids
+            .iter()
+            .chain(account.exclude_vpc_ids.iter())
+        {
+            if id.get_ref() != NO_VPC && !vpc_id.is_match(id.get_ref()) {
+                problems.push(problem(
+                    contents,
+                    id.span(),
+                    format!(
+                        "VPC id {:?} is not like vpc-0123456789abcdef0",
+                        id.get_ref()
+                    ),
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Checks a config against the schema, rejecting unknown keys, and checks
+/// its role ARNs, VPC ids and ranges, warning about unknown regions. Returns
+/// every problem found, in the order they appear in the file.
+pub fn check_config(contents: &str) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = vec![];
+
+    let document = toml::from_str::<Shape>(contents)
+        .and_then(|shape| toml::Deserializer::new(contents).deserialize_any(NodeSeed(&shape)));
+    if let Ok(Node::Table(entries)) = document {
+        check_table(contents, "", &entries, &mut problems);
+    }
+    // Serde stops at the first problem, which is only new when it isn't one
+    // of the keys or values reported above, e.g. a missing key or a wrong
+    // type.
+    if let Err(e) = toml::from_str::<AppConfig>(contents) {
+        let span = e.span().unwrap_or(0..0);
+        let first = problem(contents, span, e.message().trim().to_string());
+        let reported = problems
+            .iter()
+            .any(|problem| (problem.line, problem.column) == (first.line, first.column));
+        if !reported {
+            problems.push(first);
+        }
+    }
+    if let Ok(conf) = toml::from_str::<LocatedConfig>(contents) {
+        problems.append(&mut check_values(contents, &conf));
+    }
+
+    problems.sort_by_key(|problem| (problem.line, problem.column));
+    problems
+}
+
+/// Checks a config file and prints every problem with its location. It is
+/// valid when all of them are warnings.
+pub fn validate(file_name: &str) -> Result<(), Error> {
+    let contents = fs::read_to_string(file_name).map_err(|e| Error::File {
+        path: file_name.to_string(),
+        message: format!("error reading config file: {}", e),
+    })?;
+
+    let problems = check_config(&contents);
+    for problem in problems.iter() {
+        println!("{}:{}", file_name, problem);
+    }
+
+    let errors = problems.iter().filter(|problem| !problem.warning).count();
+    if errors == 0 {
+        println!("{} is valid", file_name);
+        return Ok(());
+    }
+    Err(Error::Config(format!(
+        "{} problems found in {}",
+        errors, file_name
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r#"name = "janitor"
+run_option = "plan"
+days = 14
+include_tags = ["env!=prod"]
+
+[[scoring.application]]
+metric_name = "RequestCount"
+stat = "Sum"
+threshold = 0.0
+weight = 1.0
+dimension = "load_balancer"
+
+[limits]
+max_account_share = 0.25
+
+[aws]
+session_duration_seconds = 3600
+
+[[aws.accounts]]
+iam_role = "arn:aws:iam::123456789012:role/lb-janitor"
+regions = ["ap-southeast-1"]
+vpc_ids = ["vpc-0123456789abcdef0", "no-vpc"]
+exclude_names = ["k8s-*"]
+"#;
+
+    fn problems(contents: &str) -> Vec<String> {
+        check_config(contents)
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_config() {
+        assert_eq!(problems(VALID), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_every_unknown_key() {
+        let contents = r#"name = "janitor"
+run_option = "plan"
+days = 14
+bogus = 1
+
+[[scoring.classic]]
+metric_name = "RequestCount"
+stat = "Sum"
+threshold = 0.0
+weight = 1.0
+colour = "red"
+
+[retry]
+max_attemps = 3
+
+[foo.bar]
+x = 1
+
+[aws]
+[[aws.accounts]]
+iam_role = "arn:aws:iam::123456789012:role/lb-janitor"
+regions = ["ap-southeast-1"]
+vpcs = []
+"#;
+        assert_eq!(
+            problems(contents),
+            vec![
+                "4:1: unknown key `bogus`",
+                "11:1: unknown key `colour` in [scoring.classic]",
+                "14:1: unknown key `max_attemps` in [retry]",
+                "16:2: unknown key `foo`",
+                "23:1: unknown key `vpcs` in [aws.accounts]",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_every_invalid_value() {
+        let contents = r#"name = "janitor"
+run_option = "delet"
+days = 999
+include_tags = ["env=prod", "=x"]
+
+[[scoring.network]]
+metric_name = "ActiveFlowCount"
+stat = "Sum"
+threshold = 0.0
+weight = 1.0
+dimension = "Nope"
+
+[aws]
+session_duration_seconds = 60
+
+[[aws.accounts]]
+iam_role = "arn:aws:iam::1234:role/x"
+regions = ["mars-north-1"]
+vpc_ids = ["vpc-12"]
+"#;
+        let problems = problems(contents);
+        assert_eq!(problems.len(), 8, "{:#?}", problems);
+        assert!(problems[0].starts_with("2:14: unknown run_option \"delet\""));
+        assert!(problems[1].starts_with("3:8: invalid days"));
+        assert!(problems[2].starts_with("4:29: invalid tag selector \"=x\""));
+        assert!(problems[3].starts_with("11:13: unknown variant `Nope`"));
+        assert_eq!(
+            problems[4],
+            "14:28: session_duration_seconds must be between 900 and 43200, got 60"
+        );
+        assert!(problems[5].starts_with("17:12: iam_role \"arn:aws:iam::1234:role/x\""));
+        assert_eq!(
+            problems[6],
+            "18:12: warning: unknown region \"mars-north-1\""
+        );
+        assert!(problems[7].starts_with("19:12: VPC id \"vpc-12\""));
+    }
+
+    #[test]
+    fn reads_the_known_keys_from_the_models() {
+        let account = known_keys("aws.accounts").unwrap();
+        assert!(account.contains(&"include_vpc_ids"));
+        assert!(account.contains(&"vpc_ids"));
+        assert!(known_keys("scoring.gateway")
+            .unwrap()
+            .contains(&"target_types"));
+        assert_eq!(known_keys("aws.accounts.regions"), None);
+    }
+
+    #[test]
+    fn locates_unknown_dotted_keys() {
+        let contents = VALID.replace(
+            "days = 14\n",
+            "days = 14\nretry.max_attemps = 3\nscoring.bogus.x = 1\n",
+        );
+        assert_eq!(
+            problems(&contents),
+            vec![
+                "4:7: unknown key `max_attemps` in [retry]",
+                "5:9: unknown key `bogus` in [scoring]",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_warns_about_unknown_regions() {
+        let contents = VALID.replace("ap-southeast-1", "ap-southeast-9");
+        let problems = check_config(&contents);
+        assert_eq!(problems.len(), 1, "{:#?}", problems);
+        assert!(problems[0].warning);
+        assert_eq!(
+            problems[0].to_string(),
+            "21:12: warning: unknown region \"ap-southeast-9\""
+        );
+    }
+
+    #[test]
+    fn reports_a_type_error_with_the_unknown_keys() {
+        let contents =
+            "name = \"t\"\nrun_option = \"plan\"\ndays = \"x\"\nfoo = 1\n[aws]\naccounts = []\n";
+        assert_eq!(
+            problems(contents),
+            vec![
+                "3:8: invalid type: string \"x\", expected i64",
+                "4:1: unknown key `foo`",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_a_syntax_error_once() {
+        let problems = problems("name = \"t\"\ndays = \n");
+        assert_eq!(problems.len(), 1, "{:#?}", problems);
+        assert!(problems[0].starts_with("2:8: "));
+    }
+}